@@ -0,0 +1,278 @@
+//! This module aligns the I-frame streams of an M3U8 master playlist with its variant streams.
+//! Each I-frame stream is matched to the variant streams it provides trick play for, either by
+//! resolution, video codec and video range, or by the naming convention of their URIs, so that
+//! the I-frame streams can be ordered to mirror a sorted variant ladder, and variant streams
+//! without trick play can be found.
+
+use crate::codecs::Codec;
+use crate::parser::{IFrameStream, MasterPlaylist, StreamVariant, VideoRange};
+use serde::Deserialize;
+
+/// The separators between the name of a variant stream and the I-frame
+/// marker in the name of its I-frame stream.
+const SEPARATORS: [char; 3] = ['-', '_', '.'];
+
+/// The markers that name an I-frame stream after its variant stream, longest
+/// first.
+const IFRAME_MARKERS: [&str; 4] = ["i-frames", "i-frame", "iframes", "iframe"];
+
+/// How I-frame streams are matched to variant streams. Either way, an
+/// I-frame stream only matches the variant streams of its own pathway, and
+/// audio-only variant streams, with neither a video codec nor a RESOLUTION,
+/// are left out.
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum IFrameMatch {
+    /// By RESOLUTION, video codec family and VIDEO-RANGE. Both streams must
+    /// name a video codec of the same family, a resolution missing from
+    /// either stream does not rule out a match, and a missing VIDEO-RANGE is
+    /// SDR.
+    #[default]
+    Attributes,
+    /// By URI, where the I-frame stream of `vod.m3u8` is named such as
+    /// `vod-iframe.m3u8`, `vod_iframes.m3u8` or `vod.iframe.m3u8`.
+    Uri,
+}
+
+/// The streams that have no counterpart, by URI.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IFrameAlignment {
+    /// The variant streams that no I-frame stream matches.
+    pub unmatched_variants: Vec<String>,
+    /// The I-frame streams that match no variant stream.
+    pub unmatched_frames: Vec<String>,
+}
+
+impl IFrameAlignment {
+    /// Whether every variant stream has an I-frame stream and the other way
+    /// around.
+    pub fn is_complete(&self) -> bool {
+        self.unmatched_variants.is_empty() && self.unmatched_frames.is_empty()
+    }
+}
+
+impl IFrameMatch {
+    /// Whether an I-frame stream provides trick play for a variant stream.
+    pub fn matches(self, variant: &StreamVariant, frame: &IFrameStream) -> bool {
+        if variant.pathway_id != frame.pathway_id || !has_video(variant) {
+            return false;
+        }
+        match self {
+            IFrameMatch::Attributes => {
+                let family = |codec: Option<Codec>| codec?.video_family();
+                let video_family = family(variant.video_codec());
+                let sdr = VideoRange::Sdr;
+                video_family.is_some()
+                    && video_family == family(frame.video_codec())
+                    && compatible(variant.resolution, frame.resolution)
+                    && variant.video_range.as_ref().unwrap_or(&sdr)
+                        == frame.video_range.as_ref().unwrap_or(&sdr)
+            }
+            IFrameMatch::Uri => iframe_stem(&frame.uri) == Some(stem(&variant.uri)),
+        }
+    }
+}
+
+impl MasterPlaylist {
+    /// Orders the I-frame streams to mirror the variant streams: each I-frame
+    /// stream moves to the position of the first variant stream it matches,
+    /// and the ones that match no variant stream go last. I-frame streams in
+    /// the same position keep their order. Meant to follow `sort_stream`.
+    pub fn align_iframes(&mut self, by: IFrameMatch) -> IFrameAlignment {
+        let variants = &self.variants;
+        self.frames.sort_by_key(|frame| {
+            let position = variants
+                .iter()
+                .position(|variant| by.matches(variant, frame));
+            (position.is_none(), position)
+        });
+        self.iframe_alignment(by)
+    }
+
+    /// Finds the video variant streams that no I-frame stream matches, and
+    /// the I-frame streams that match no variant stream, without reordering.
+    pub fn iframe_alignment(&self, by: IFrameMatch) -> IFrameAlignment {
+        IFrameAlignment {
+            unmatched_variants: self
+                .variants
+                .iter()
+                .filter(|variant| {
+                    has_video(variant)
+                        && !self.frames.iter().any(|frame| by.matches(variant, frame))
+                })
+                .map(|variant| variant.uri.clone())
+                .collect(),
+            unmatched_frames: self
+                .frames
+                .iter()
+                .filter(|frame| {
+                    !self
+                        .variants
+                        .iter()
+                        .any(|variant| by.matches(variant, frame))
+                })
+                .map(|frame| frame.uri.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Whether a variant stream has video, and so needs an I-frame stream
+fn has_video(variant: &StreamVariant) -> bool {
+    variant.video_codec().is_some() || variant.resolution.is_some()
+}
+
+/// Whether two attribute values can belong to the same rendition, counting
+/// a missing value as unknown.
+fn compatible<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+/// The URI without its query, fragment and file extension, such as
+/// `video/vod` for `video/vod.m3u8?token=1`.
+fn stem(uri: &str) -> &str {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let name = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[name..].rfind('.') {
+        Some(dot) if dot > 0 => &path[..name + dot],
+        _ => path,
+    }
+}
+
+/// The stem of the variant stream an I-frame stream URI is named after, if
+/// it ends in an I-frame marker, ignoring case.
+fn iframe_stem(uri: &str) -> Option<&str> {
+    let stem = stem(uri);
+    IFRAME_MARKERS.iter().find_map(|marker| {
+        let split = stem.len().checked_sub(marker.len())?;
+        let (base, suffix) = (stem.get(..split)?, &stem[split..]);
+        if !suffix.eq_ignore_ascii_case(marker) {
+            return None;
+        }
+        base.strip_suffix(SEPARATORS)
+            .filter(|base| !base.is_empty() && !base.ends_with('/'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_playlist;
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=640x360
+low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=7680000,CODECS=\"hvc1.2.4.L150.B0,mp4a.40.2\",RESOLUTION=1920x1080,VIDEO-RANGE=PQ
+hdr.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2560000,CODECS=\"avc1.4d401f,mp4a.40.2\",RESOLUTION=1280x720
+mid.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2700000,CODECS=\"avc1.4d401f,ec-3\",RESOLUTION=1280x720
+mid-atmos.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,CODECS=\"avc1.4d401e\",RESOLUTION=640x360,URI=\"low-iframe.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=40000,CODECS=\"avc1.4d400d\",RESOLUTION=320x180,URI=\"tiny_iframes.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=150000,CODECS=\"avc1.4d401f\",RESOLUTION=1280x720,URI=\"mid.iframe.m3u8\"
+";
+
+    fn uris(playlist: &MasterPlaylist) -> Vec<&str> {
+        playlist
+            .frames
+            .iter()
+            .map(|frame| frame.uri.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_align_iframes_by_attributes() {
+        let mut playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        playlist.variants.reverse();
+        let alignment = playlist.align_iframes(IFrameMatch::Attributes);
+
+        assert_eq!(
+            uris(&playlist),
+            ["mid.iframe.m3u8", "low-iframe.m3u8", "tiny_iframes.m3u8"]
+        );
+        assert_eq!(alignment.unmatched_variants, ["hdr.m3u8"]);
+        assert_eq!(alignment.unmatched_frames, ["tiny_iframes.m3u8"]);
+        assert!(!alignment.is_complete());
+    }
+
+    #[test]
+    fn test_align_iframes_skips_audio_only_variants() {
+        let mut playlist = parse_playlist(
+            "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"
+audio.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2560000,CODECS=\"avc1.4d401f,mp4a.40.2\",RESOLUTION=1280x720
+mid.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=640x360
+low.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,CODECS=\"avc1.4d401e\",RESOLUTION=640x360,URI=\"low-iframe.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=150000,CODECS=\"avc1.4d401f\",RESOLUTION=1280x720,URI=\"mid-iframe.m3u8\"
+",
+        )
+        .expect("Failed to parse playlist");
+        let alignment = playlist.align_iframes(IFrameMatch::Attributes);
+
+        assert_eq!(uris(&playlist), ["mid-iframe.m3u8", "low-iframe.m3u8"]);
+        assert!(alignment.is_complete());
+
+        // Without a video codec on both sides, the streams do not match
+        playlist.frames[0].codecs = None;
+        let alignment = playlist.iframe_alignment(IFrameMatch::Attributes);
+        assert_eq!(alignment.unmatched_variants, ["mid.m3u8"]);
+        assert_eq!(alignment.unmatched_frames, ["mid-iframe.m3u8"]);
+    }
+
+    #[test]
+    fn test_align_iframes_by_uri() {
+        let mut playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        playlist.variants.reverse();
+        let alignment = playlist.align_iframes(IFrameMatch::Uri);
+
+        assert_eq!(
+            uris(&playlist),
+            ["mid.iframe.m3u8", "low-iframe.m3u8", "tiny_iframes.m3u8"]
+        );
+        assert_eq!(alignment.unmatched_variants, ["mid-atmos.m3u8", "hdr.m3u8"]);
+        assert_eq!(alignment.unmatched_frames, ["tiny_iframes.m3u8"]);
+    }
+
+    #[test]
+    fn test_iframe_stem() {
+        assert_eq!(iframe_stem("vod-iframe.m3u8"), Some("vod"));
+        assert_eq!(
+            iframe_stem("1080p/vod_I-Frames.m3u8?token=1"),
+            Some("1080p/vod")
+        );
+        assert_eq!(
+            iframe_stem("https://cdn.example.com/vod.iframe.m3u8"),
+            Some("https://cdn.example.com/vod")
+        );
+        assert_eq!(iframe_stem("vod.m3u8"), None);
+        assert_eq!(iframe_stem("iframe.m3u8"), None);
+        assert_eq!(iframe_stem("hd/-iframe.m3u8"), None);
+        assert_eq!(
+            stem("https://cdn.example.com/v1.2/vod"),
+            "https://cdn.example.com/v1.2/vod"
+        );
+    }
+
+    #[test]
+    fn test_matches_only_same_pathway() {
+        let playlist = parse_playlist(
+            "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640x360,PATHWAY-ID=\"CDN-A\"
+low.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,RESOLUTION=640x360,PATHWAY-ID=\"CDN-B\",URI=\"low-iframe.m3u8\"
+",
+        )
+        .expect("Failed to parse playlist");
+        let alignment = playlist.iframe_alignment(IFrameMatch::Uri);
+
+        assert_eq!(alignment.unmatched_variants, ["low.m3u8"]);
+        assert_eq!(alignment.unmatched_frames, ["low-iframe.m3u8"]);
+    }
+}
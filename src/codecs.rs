@@ -0,0 +1,545 @@
+//! This module decodes the CODECS attribute of variant streams and I-frame streams, a comma
+//! separated list of RFC 6381 codec strings such as `avc1.640028,mp4a.40.2`. Each codec is
+//! identified by its sample entry and, for the formats known here, its parameters such as the
+//! profile and level are decoded. Codecs that are unknown or malformed are kept as written.
+
+use crate::parser::{IFrameStream, StreamVariant};
+use serde::Deserialize;
+use std::fmt;
+
+/// A codec of a CODECS attribute
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Codec {
+    /// The codec string as written, such as `hvc1.2.4.L150.90`
+    pub id: String,
+    pub kind: CodecKind,
+}
+
+/// The format of a codec and its decoded parameters
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CodecKind {
+    /// `avc1` or `avc3`
+    Avc(Avc),
+    /// `hvc1` or `hev1`
+    Hevc(Hevc),
+    /// `av01`
+    Av1(Av1),
+    /// `vp09`
+    Vp9(Vp9),
+    /// `dvh1` or `dvhe` over HEVC, `dva1` or `dvav` over AVC, or `dav1`
+    /// over AV1
+    DolbyVision(DolbyVision),
+    /// `mp4a`
+    Mp4a(Mp4a),
+    /// `ac-3`
+    Ac3,
+    /// `ec-3`
+    Ec3,
+    /// `ac-4`
+    Ac4,
+    /// `Opus`
+    Opus,
+    /// `fLaC`
+    Flac,
+    /// `stpp`, TTML subtitles
+    Stpp,
+    /// `wvtt`, WebVTT subtitles
+    Wvtt,
+    /// A codec not decoded here, or one whose parameters are malformed
+    Other,
+}
+
+/// The kind of media a codec carries
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum CodecType {
+    Video,
+    Audio,
+    Subtitles,
+}
+
+/// The family of a video codec, in the default order of sorting by video
+/// codec
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum VideoCodecFamily {
+    Avc,
+    Hevc,
+    DolbyVision,
+    Vp9,
+    Av1,
+}
+
+/// The family of an audio codec, in the order of sorting by audio codec
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum AudioCodecFamily {
+    Aac,
+    Ac3,
+    Ec3,
+    Ac4,
+    Opus,
+    Flac,
+}
+
+/// The tier of an HEVC or AV1 stream
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Tier {
+    Main,
+    High,
+}
+
+/// H.264 parameters. `level` is the level_idc, ten times the level.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Avc {
+    pub profile: u8,
+    pub constraints: u8,
+    pub level: u8,
+}
+
+/// H.265 parameters. `level` is the general_level_idc, thirty times the
+/// level.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Hevc {
+    pub profile: u8,
+    pub tier: Tier,
+    pub level: u8,
+}
+
+/// AV1 parameters. `level` is the seq_level_idx.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Av1 {
+    pub profile: u8,
+    pub level: u8,
+    pub tier: Tier,
+    pub bit_depth: u8,
+}
+
+/// VP9 parameters. `level` is ten times the level.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Vp9 {
+    pub profile: u8,
+    pub level: u8,
+    pub bit_depth: u8,
+}
+
+/// Dolby Vision parameters
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DolbyVision {
+    pub profile: u8,
+    pub level: u8,
+}
+
+/// MPEG-4 audio parameters. The object type indication is 0x40 for MPEG-4
+/// audio, whose audio object type is then given as well, such as 2 for
+/// AAC-LC or 5 for HE-AAC.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Mp4a {
+    pub object_type: u8,
+    pub audio_object_type: Option<u8>,
+}
+
+/// Splits a CODECS attribute into its codecs, skipping empty entries
+pub fn parse_codecs(codecs: &str) -> Vec<Codec> {
+    codecs
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(Codec::parse)
+        .collect()
+}
+
+impl Codec {
+    /// Decodes a single codec string
+    pub fn parse(id: &str) -> Codec {
+        let codec = id.split('/').next().unwrap_or_default();
+        let mut parts = codec.split('.');
+        let entry = parts.next().unwrap_or_default();
+        let params: Vec<&str> = parts.collect();
+
+        let kind = match entry {
+            "avc1" | "avc3" => parse_avc(&params).map(CodecKind::Avc),
+            "hvc1" | "hev1" => parse_hevc(&params).map(CodecKind::Hevc),
+            "av01" => parse_av1(&params).map(CodecKind::Av1),
+            "vp09" => parse_vp9(&params).map(CodecKind::Vp9),
+            "dvh1" | "dvhe" | "dva1" | "dvav" | "dav1" => {
+                parse_dolby_vision(&params).map(CodecKind::DolbyVision)
+            }
+            "mp4a" => parse_mp4a(&params).map(CodecKind::Mp4a),
+            "ac-3" => Some(CodecKind::Ac3),
+            "ec-3" => Some(CodecKind::Ec3),
+            "ac-4" => Some(CodecKind::Ac4),
+            "Opus" | "opus" => Some(CodecKind::Opus),
+            "fLaC" | "flac" => Some(CodecKind::Flac),
+            "stpp" => Some(CodecKind::Stpp),
+            "wvtt" => Some(CodecKind::Wvtt),
+            _ => None,
+        };
+
+        Codec {
+            id: id.to_string(),
+            kind: kind.unwrap_or(CodecKind::Other),
+        }
+    }
+
+    /// The sample entry of the codec, such as `avc1`
+    pub fn sample_entry(&self) -> &str {
+        self.id.split(['.', '/']).next().unwrap_or_default()
+    }
+
+    /// The kind of media the codec carries, judged by its sample entry, or
+    /// `None` if the sample entry is unknown
+    pub fn codec_type(&self) -> Option<CodecType> {
+        match self.sample_entry() {
+            "avc1" | "avc3" | "hvc1" | "hev1" | "av01" | "vp08" | "vp09" | "dvh1" | "dvhe"
+            | "dva1" | "dvav" | "dav1" | "mp4v" => Some(CodecType::Video),
+            "mp4a" | "ac-3" | "ec-3" | "ac-4" | "Opus" | "opus" | "fLaC" | "flac" | "alac"
+            | "mha1" | "mhm1" => Some(CodecType::Audio),
+            "stpp" | "wvtt" => Some(CodecType::Subtitles),
+            _ => None,
+        }
+    }
+
+    /// The family of a video codec, judged by its sample entry
+    pub fn video_family(&self) -> Option<VideoCodecFamily> {
+        match self.sample_entry() {
+            "avc1" | "avc3" => Some(VideoCodecFamily::Avc),
+            "hvc1" | "hev1" => Some(VideoCodecFamily::Hevc),
+            "dvh1" | "dvhe" | "dva1" | "dvav" | "dav1" => Some(VideoCodecFamily::DolbyVision),
+            "vp09" => Some(VideoCodecFamily::Vp9),
+            "av01" => Some(VideoCodecFamily::Av1),
+            _ => None,
+        }
+    }
+
+    /// The family of an audio codec, judged by its sample entry
+    pub fn audio_family(&self) -> Option<AudioCodecFamily> {
+        match self.sample_entry() {
+            "mp4a" => Some(AudioCodecFamily::Aac),
+            "ac-3" => Some(AudioCodecFamily::Ac3),
+            "ec-3" => Some(AudioCodecFamily::Ec3),
+            "ac-4" => Some(AudioCodecFamily::Ac4),
+            "Opus" | "opus" => Some(AudioCodecFamily::Opus),
+            "fLaC" | "flac" => Some(AudioCodecFamily::Flac),
+            _ => None,
+        }
+    }
+
+    /// Ten times the level of a video codec, such as 51 for level 5.1.
+    /// Dolby Vision levels are numbered 1 to 13 rather than versioned, so
+    /// they are on a scale of their own: levels only compare within a
+    /// family.
+    pub fn level(&self) -> Option<u32> {
+        match self.kind {
+            CodecKind::Avc(avc) => Some(avc.level as u32),
+            CodecKind::Hevc(hevc) => Some(hevc.level as u32 / 3),
+            CodecKind::Av1(av1) => {
+                Some((2 + (av1.level as u32 >> 2)) * 10 + (av1.level as u32 & 3))
+            }
+            CodecKind::Vp9(vp9) => Some(vp9.level as u32),
+            CodecKind::DolbyVision(dolby_vision) => Some(dolby_vision.level as u32 * 10),
+            _ => None,
+        }
+    }
+
+    /// The profile of a Dolby Vision codec. The brands that may follow a
+    /// supplemental codec, such as `/db4h`, are ignored.
+    pub fn dolby_vision_profile(&self) -> Option<u8> {
+        match self.kind {
+            CodecKind::DolbyVision(dolby_vision) => Some(dolby_vision.profile),
+            _ => None,
+        }
+    }
+
+    /// The tier of an HEVC or AV1 codec
+    pub fn tier(&self) -> Option<Tier> {
+        match self.kind {
+            CodecKind::Hevc(hevc) => Some(hevc.tier),
+            CodecKind::Av1(av1) => Some(av1.tier),
+            _ => None,
+        }
+    }
+}
+
+impl StreamVariant {
+    /// The codecs of the CODECS attribute, in order
+    pub fn codec_list(&self) -> Vec<Codec> {
+        self.codecs.as_deref().map(parse_codecs).unwrap_or_default()
+    }
+
+    /// The first video codec of the CODECS attribute
+    pub fn video_codec(&self) -> Option<Codec> {
+        first_of_type(self.codec_list(), CodecType::Video)
+    }
+
+    /// The Dolby Vision profile of the video codec, or failing that of the
+    /// SUPPLEMENTAL-CODECS attribute, such as 8 for `dvh1.08.07/db4h`
+    pub fn dolby_vision_profile(&self) -> Option<u8> {
+        let supplemental = self
+            .supplemental_codecs
+            .as_deref()
+            .map(parse_codecs)
+            .unwrap_or_default();
+        self.codec_list()
+            .into_iter()
+            .chain(supplemental)
+            .find_map(|codec| codec.dolby_vision_profile())
+    }
+
+    /// The audio codecs of the CODECS attribute, in order
+    pub fn audio_codecs(&self) -> Vec<Codec> {
+        self.codec_list()
+            .into_iter()
+            .filter(|codec| codec.codec_type() == Some(CodecType::Audio))
+            .collect()
+    }
+}
+
+impl IFrameStream {
+    /// The codecs of the CODECS attribute, in order
+    pub fn codec_list(&self) -> Vec<Codec> {
+        self.codecs.as_deref().map(parse_codecs).unwrap_or_default()
+    }
+
+    /// The first video codec of the CODECS attribute
+    pub fn video_codec(&self) -> Option<Codec> {
+        first_of_type(self.codec_list(), CodecType::Video)
+    }
+
+    /// The Dolby Vision profile of the video codec
+    pub fn dolby_vision_profile(&self) -> Option<u8> {
+        self.video_codec()
+            .and_then(|codec| codec.dolby_vision_profile())
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+fn first_of_type(codecs: Vec<Codec>, codec_type: CodecType) -> Option<Codec> {
+    codecs
+        .into_iter()
+        .find(|codec| codec.codec_type() == Some(codec_type))
+}
+
+fn hex(param: &str) -> Option<u8> {
+    u8::from_str_radix(param, 16).ok()
+}
+
+fn decimal(param: &str) -> Option<u8> {
+    param.parse().ok()
+}
+
+/// `avc1.PPCCLL` with hexadecimal profile, constraint flags and level, or
+/// the legacy `avc1.PP.LL` with decimal profile and level
+fn parse_avc(params: &[&str]) -> Option<Avc> {
+    match params {
+        [profile_constraints_level]
+            if profile_constraints_level.len() == 6 && profile_constraints_level.is_ascii() =>
+        {
+            Some(Avc {
+                profile: hex(&profile_constraints_level[0..2])?,
+                constraints: hex(&profile_constraints_level[2..4])?,
+                level: hex(&profile_constraints_level[4..6])?,
+            })
+        }
+        [profile, level] => Some(Avc {
+            profile: decimal(profile)?,
+            constraints: 0,
+            level: decimal(level)?,
+        }),
+        _ => None,
+    }
+}
+
+/// `hvc1.[A-C]P.F.TL[.C...]` with an optional profile space, the profile,
+/// the compatibility flags, the tier and level, and the constraint flags
+fn parse_hevc(params: &[&str]) -> Option<Hevc> {
+    let [profile, _compatibility, tier_level, ..] = params else {
+        return None;
+    };
+    let profile = profile.trim_start_matches(['A', 'B', 'C']);
+    let tier = match tier_level.get(..1)? {
+        "L" => Tier::Main,
+        "H" => Tier::High,
+        _ => return None,
+    };
+    Some(Hevc {
+        profile: decimal(profile)?,
+        tier,
+        level: decimal(&tier_level[1..])?,
+    })
+}
+
+/// `av01.P.LLT.DD[...]` with the profile, level, tier and bit depth
+fn parse_av1(params: &[&str]) -> Option<Av1> {
+    let [profile, level_tier, bit_depth, ..] = params else {
+        return None;
+    };
+    let split = level_tier.len().checked_sub(1)?;
+    let tier = match level_tier.get(split..)? {
+        "M" => Tier::Main,
+        "H" => Tier::High,
+        _ => return None,
+    };
+    Some(Av1 {
+        profile: decimal(profile)?,
+        level: decimal(level_tier.get(..split)?)?,
+        tier,
+        bit_depth: decimal(bit_depth)?,
+    })
+}
+
+/// `vp09.PP.LL.DD[...]` with the profile, level and bit depth
+fn parse_vp9(params: &[&str]) -> Option<Vp9> {
+    let [profile, level, bit_depth, ..] = params else {
+        return None;
+    };
+    Some(Vp9 {
+        profile: decimal(profile)?,
+        level: decimal(level)?,
+        bit_depth: decimal(bit_depth)?,
+    })
+}
+
+/// `dvh1.PP.LL`, or the same with another Dolby Vision sample entry, with
+/// the profile and level
+fn parse_dolby_vision(params: &[&str]) -> Option<DolbyVision> {
+    let [profile, level] = params else {
+        return None;
+    };
+    Some(DolbyVision {
+        profile: decimal(profile)?,
+        level: decimal(level)?,
+    })
+}
+
+/// `mp4a.OO[.A]` with the hexadecimal object type indication and, for
+/// MPEG-4 audio, the decimal audio object type
+fn parse_mp4a(params: &[&str]) -> Option<Mp4a> {
+    match params {
+        [object_type] => Some(Mp4a {
+            object_type: hex(object_type)?,
+            audio_object_type: None,
+        }),
+        [object_type, audio_object_type] => Some(Mp4a {
+            object_type: hex(object_type)?,
+            audio_object_type: Some(decimal(audio_object_type)?),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_playlist;
+
+    #[test]
+    fn test_parse_codecs() {
+        let kinds: Vec<_> = parse_codecs(
+            "avc1.640028, avc1.66.30,hvc1.2.4.L150.90,hev1.A1.6.H120.B0,av01.0.08M.10,vp09.02.10.10.01.09.16.09.01,dvh1.05.06,dvav.09.05,dav1.10.09,mp4a.40.2,mp4a.6B,ec-3,ac-4.02.01.01,Opus,fLaC,stpp.ttml.im1t,wvtt,avc1.bogus,xyz1.2",
+        )
+        .into_iter()
+        .map(|codec| codec.kind)
+        .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                CodecKind::Avc(Avc {
+                    profile: 100,
+                    constraints: 0,
+                    level: 40
+                }),
+                CodecKind::Avc(Avc {
+                    profile: 66,
+                    constraints: 0,
+                    level: 30
+                }),
+                CodecKind::Hevc(Hevc {
+                    profile: 2,
+                    tier: Tier::Main,
+                    level: 150
+                }),
+                CodecKind::Hevc(Hevc {
+                    profile: 1,
+                    tier: Tier::High,
+                    level: 120
+                }),
+                CodecKind::Av1(Av1 {
+                    profile: 0,
+                    level: 8,
+                    tier: Tier::Main,
+                    bit_depth: 10
+                }),
+                CodecKind::Vp9(Vp9 {
+                    profile: 2,
+                    level: 10,
+                    bit_depth: 10
+                }),
+                CodecKind::DolbyVision(DolbyVision {
+                    profile: 5,
+                    level: 6
+                }),
+                CodecKind::DolbyVision(DolbyVision {
+                    profile: 9,
+                    level: 5
+                }),
+                CodecKind::DolbyVision(DolbyVision {
+                    profile: 10,
+                    level: 9
+                }),
+                CodecKind::Mp4a(Mp4a {
+                    object_type: 0x40,
+                    audio_object_type: Some(2)
+                }),
+                CodecKind::Mp4a(Mp4a {
+                    object_type: 0x6B,
+                    audio_object_type: None
+                }),
+                CodecKind::Ec3,
+                CodecKind::Ac4,
+                CodecKind::Opus,
+                CodecKind::Flac,
+                CodecKind::Stpp,
+                CodecKind::Wvtt,
+                CodecKind::Other,
+                CodecKind::Other,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variant_codec_accessors() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,CODECS=\"mp4a.40.2,hvc1.2.4.L93.90,ec-3,wvtt\"
+video.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000
+audio.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,CODECS=\"avc1.64001f\",URI=\"iframe.m3u8\"
+";
+        let playlist = parse_playlist(input).expect("Failed to parse playlist");
+
+        let variant = &playlist.variants[0];
+        assert_eq!(
+            variant.video_codec().map(|codec| codec.id),
+            Some("hvc1.2.4.L93.90".to_string())
+        );
+        let audio: Vec<_> = variant
+            .audio_codecs()
+            .into_iter()
+            .map(|codec| codec.to_string())
+            .collect();
+        assert_eq!(audio, ["mp4a.40.2", "ec-3"]);
+
+        assert_eq!(playlist.variants[1].video_codec(), None);
+        assert_eq!(
+            playlist.frames[0]
+                .video_codec()
+                .map(|codec| codec.codec_type()),
+            Some(Some(CodecType::Video))
+        );
+    }
+}
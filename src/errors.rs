@@ -1,4 +1,4 @@
-use nom::{error::Error as NomError, Err as NomErr};
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,12 +9,98 @@ pub enum PlaylistError {
     #[error("Failed to read the playlist file: {0}")]
     IoError(#[from] std::io::Error),
 
-    #[error("Nom parsing error: {0:?}")]
-    ParseError(NomErr<NomError<String>>),
+    #[error("Failed to parse the playlist at {0}")]
+    ParseError(ParseError),
+
+    #[error("Missing a required attribute at {0}")]
+    MissingAttribute(ParseError),
+
+    #[error("Missing the URI line of the variant stream at {0}")]
+    MissingUri(ParseError),
+
+    #[error("Missing the URI line of the media segment at {0}")]
+    MissingSegmentUri(ParseError),
 
     #[error("Parsing incomplete error: {0:?}")]
     Incomplete(String),
 
     #[error("Invalid location. Provide a valid URL or file path.")]
     InvalidLocation,
+
+    #[error("Reference to undefined variable {name}{}", located_at(.location))]
+    UndefinedVariable {
+        name: String,
+        /// Where the reference is, when found while parsing
+        location: Option<Box<ParseError>>,
+    },
+
+    #[error("Variable defined twice at {0}")]
+    DuplicateVariable(ParseError),
+
+    #[error("Invalid validator configuration: {0}")]
+    ConfigError(String),
+
+    #[error("Invalid sort profile: {0}")]
+    ProfileError(String),
+}
+
+/// The location of an error, if known, as a suffix of its message
+fn located_at(location: &Option<Box<ParseError>>) -> String {
+    location
+        .as_ref()
+        .map(|location| format!(" at {}", location))
+        .unwrap_or_default()
+}
+
+/// A syntax error in a playlist, located by its 1-based line and column.
+/// `length` is the number of characters of the offending text on the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub tag: Option<String>,
+    pub attribute: Option<String>,
+    pub reason: String,
+    pub source_line: String,
+}
+
+/// Renders the location and reason, followed by the offending line with the
+/// offending text underlined
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        if let Some(ref tag) = self.tag {
+            write!(f, ", {}", tag)?;
+        }
+        if let Some(ref attribute) = self.attribute {
+            write!(f, " attribute {}", attribute)?;
+        }
+        writeln!(f, ": {}", self.reason)?;
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column - 1),
+            "^".repeat(self.length)
+        )
+    }
+}
+
+/// A problem in a playlist that lenient parsing recovered from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub error: ParseError,
+    /// What the parser did instead of failing
+    pub recovery: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n  = {}", self.error, self.recovery)
+    }
 }
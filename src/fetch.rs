@@ -1,15 +1,20 @@
 //! This module provides asynchronous functions to fetch and parse a playlist from a URL or a local file.
 //! It supports fetching content from HTTP/HTTPS locations as well as reading playlists from local file paths.
-//! The fetched content is parsed into a `MasterPlaylist` using a custom parser.
+//! The fetched content is parsed into a `MasterPlaylist` using a custom parser, or into a
+//! `Playlist` when the kind of playlist is not known up front.
 
-use crate::parser::MasterPlaylist;
+use crate::errors::Diagnostic;
+use crate::parser::{
+    parse_any_playlist, parse_playlist_with_options, MasterPlaylist, ParseOptions, Playlist,
+};
 use crate::{errors::PlaylistError, parser::parse_playlist};
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tracing::{error, info};
 
-/// Async function to fetch and parse the playlist using the custom parser
+/// Async function to fetch and parse the playlist using the custom parser. Parsing is
+/// lenient, see `fetch_playlist_with_options` to get the recoveries or to parse strictly.
 pub async fn fetch_playlist(location: &str) -> Result<MasterPlaylist, PlaylistError> {
     info!("Fetching playlist from {}", location);
 
@@ -19,6 +24,35 @@ pub async fn fetch_playlist(location: &str) -> Result<MasterPlaylist, PlaylistEr
     Ok(playlist)
 }
 
+/// Async function to fetch and parse the playlist with the given parse options, returning
+/// the recoveries made in lenient mode along with the playlist
+pub async fn fetch_playlist_with_options(
+    location: &str,
+    options: ParseOptions,
+) -> Result<(MasterPlaylist, Vec<Diagnostic>), PlaylistError> {
+    info!("Fetching playlist from {}", location);
+
+    let content = fetch_content(location).await?;
+    parse_playlist_with_options(&content, options)
+}
+
+/// Async function to fetch and parse the playlist with its EXT-X-DEFINE variables resolved.
+/// QUERYPARAM variables take their values from the query string of `location`.
+pub async fn fetch_resolved_playlist(location: &str) -> Result<MasterPlaylist, PlaylistError> {
+    let playlist = fetch_playlist(location).await?;
+    playlist.resolve_variables(Some(location))
+}
+
+/// Async function to fetch and parse a playlist that may be either a master or a media playlist
+pub async fn fetch_any_playlist(location: &str) -> Result<Playlist, PlaylistError> {
+    info!("Fetching playlist from {}", location);
+
+    let content = fetch_content(location).await?;
+    let playlist = parse_any_playlist(&content)?;
+
+    Ok(playlist)
+}
+
 /// Async helper function to fetch content from a URL or local file
 async fn fetch_content(location: &str) -> Result<String, PlaylistError> {
     if location.starts_with("http://") || location.starts_with("https://") {
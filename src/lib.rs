@@ -1,6 +1,7 @@
 //! # Playlist Parse And Sort Library
 //!
-//! This library provides functionality to fetch, parse, and sort M3U8 master playlists.
+//! This library provides functionality to fetch, parse, and sort M3U8 master playlists, and to
+//! fetch and parse M3U8 media playlists.
 //! It includes support for fetching playlists from both HTTP/HTTPS URLs and local file paths,
 //! parsing them into a `MasterPlaylist` structure, and sorting stream variants, media tracks,
 //! and I-frame streams by various criteria.
@@ -12,8 +13,26 @@
 //!
 //! - Fetch playlists from URLs or local file paths.
 //! - Parse M3U8 master playlists into structured data (`MasterPlaylist`).
-//! - Sort streams, media tracks, and I-frame streams by multiple criteria such as bandwidth, resolution, and codecs.
-//! - Serialize sorted playlists back into M3U8 format.
+//! - Parse M3U8 media playlists and their segments (`MediaPlaylist`), or detect the kind of
+//!   playlist automatically (`Playlist`).
+//! - Decode the RFC 6381 codec strings of CODECS attributes, such as the profile and level of
+//!   `avc1.640028` (`Codec`).
+//! - Validate master playlists against the rules of RFC 8216, or the Apple HLS Authoring
+//!   Specification profile, reporting each violation with its rule ID, severity and location.
+//!   Rules can be turned on or off from a TOML configuration.
+//! - Strict parsing that locates malformed input by line and column, or lenient parsing that
+//!   recovers and returns a `Diagnostic` for every recovery made.
+//! - Sort streams, media tracks, and I-frame streams by multiple criteria such as bandwidth, resolution, and codecs,
+//!   or by custom comparators composed with the built-in criteria.
+//! - Order I-frame streams to mirror the sorted variant streams, matching them by resolution,
+//!   codec and video range or by URI, and report the streams left without a counterpart.
+//! - Describe how to filter, rewrite and sort playlists in named profiles, loaded from TOML or
+//!   YAML files (`SortProfiles`).
+//! - List, filter and clone content steering pathways.
+//! - Resolve EXT-X-DEFINE variable references, including QUERYPARAM variables taken from the
+//!   playlist URL.
+//! - Serialize sorted playlists back into M3U8 format, keeping unknown tags, comments and the
+//!   original line order.
 //!
 //! ## Examples
 //!
@@ -36,29 +55,87 @@
 //!
 //! In this example, a playlist is fetched from the specified URL and parsed into a `MasterPlaylist` structure.
 //!
+//! ### Fetching a Playlist of Either Kind
+//!
+//! ```rust
+//! use m3u8_parse_sort::errors::PlaylistError;
+//! use m3u8_parse_sort::fetch::fetch_any_playlist;
+//! use m3u8_parse_sort::parser::Playlist;
+//! use tokio;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), PlaylistError> {
+//!     let location = "tests/data/media_parse_test.m3u8";
+//!     match fetch_any_playlist(location).await? {
+//!         Playlist::Master(playlist) => println!("{} variants", playlist.variants.len()),
+//!         Playlist::Media(playlist) => println!("{} segments", playlist.segments.len()),
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ### Parsing Leniently
+//!
+//! Parsing is lenient by default, in `ParseOptions::default()` as in the entry points that take
+//! no options such as `parse_playlist` and `fetch_playlist`, which discard the recoveries made.
+//! The `_with_options` entry points return them, and `ParseOptions::strict()` fails instead.
+//!
+//! ```rust
+//! use m3u8_parse_sort::parser::{parse_playlist_with_options, ParseOptions};
+//!
+//! let input = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640by360\nlow.m3u8\n";
+//! let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default()).unwrap();
+//! assert_eq!(playlist.variants.len(), 1);
+//! assert_eq!(diagnostics[0].error.line, 2);
+//! assert_eq!(diagnostics[0].error.attribute.as_deref(), Some("RESOLUTION"));
+//! ```
+//!
 //! ### Sorting a Playlist by Bandwidth and Resolution
 //!
 //! ```rust
 //! use m3u8_parse_sort::parser::MasterPlaylist;
-//! use m3u8_parse_sort::sort::{SortStreamBy, get_sort_order};
+//! use m3u8_parse_sort::sort::{SortKey, SortStreamBy};
 //!
 //! fn sort_playlist_by_bandwidth_and_resolution(mut playlist: MasterPlaylist) {
-//!     let sort_order = (SortStreamBy::Bandwidth, SortStreamBy::Resolution);
-//!     playlist.sort_stream(sort_order);
+//!     playlist.sort_stream(&[
+//!         SortKey::descending(SortStreamBy::Bandwidth),
+//!         SortKey::ascending(SortStreamBy::Resolution).nulls_last(),
+//!     ]);
 //!     println!("Sorted playlist by bandwidth and resolution");
 //! }
 //! ```
 //!
-//! This example demonstrates how to sort streams in a playlist first by bandwidth, then by resolution.
+//! This example demonstrates how to sort streams in a playlist first by descending bandwidth,
+//! then by resolution with audio-only streams last.
+//!
+//! ### Sorting with a Custom Comparator
+//!
+//! ```rust
+//! use m3u8_parse_sort::parser::{parse_playlist, StreamVariant};
+//! use m3u8_parse_sort::sort::{by_key, Comparator, SortKey, SortStreamBy};
+//!
+//! let input = "#EXTM3U
+//! #EXT-X-STREAM-INF:BANDWIDTH=1000000
+//! https://cdn-b.example.com/low.m3u8
+//! #EXT-X-STREAM-INF:BANDWIDTH=2000000
+//! https://cdn-a.example.com/high.m3u8
+//! ";
+//! let mut playlist = parse_playlist(input).unwrap();
+//!
+//! // CDN-A first, then by descending bandwidth
+//! playlist.sort_stream_with(
+//!     by_key(|v: &StreamVariant| !v.uri.starts_with("https://cdn-a."))
+//!         .then(SortKey::descending(SortStreamBy::Bandwidth)),
+//! );
+//! assert_eq!(playlist.variants[0].uri, "https://cdn-a.example.com/high.m3u8");
+//! ```
 //!
-//! ### Fetching, Sorting, and Saving a Playlist
+//! ### Fetching, Sorting, and Writing a Playlist
 //!
 //! ```rust
 //! use m3u8_parse_sort::fetch::fetch_playlist;
-//! use m3u8_parse_sort::sort::{get_sort_order, SortStreamBy};
+//! use m3u8_parse_sort::sort::SortStreamBy;
 //! use m3u8_parse_sort::errors::PlaylistError;
-//! use std::fs::File;
-//! use std::io::Write;
 //! use tokio;
 //!
 //! #[tokio::main]
@@ -68,28 +145,38 @@
 //!     let mut playlist = fetch_playlist(location).await?;
 //!
 //!     // Sort playlist by bandwidth and codecs
-//!     let sort_order = (SortStreamBy::Bandwidth, SortStreamBy::Codecs);
-//!     playlist.sort_stream(sort_order);
+//!     playlist.sort_stream(&[SortStreamBy::Bandwidth.into(), SortStreamBy::Codecs.into()]);
 //!
-//!     // Save sorted playlist to a file
-//!     let mut file = File::create("sorted_playlist.m3u8")?;
-//!     playlist.write_to(&mut file)?;
+//!     // Write sorted playlist to any `std::io::Write`, such as a file
+//!     let mut output = Vec::new();
+//!     playlist.write_to(&mut output)?;
 //!
-//!     println!("Playlist sorted and saved to sorted_playlist.m3u8");
+//!     println!("{}", String::from_utf8_lossy(&output));
 //!     Ok(())
 //! }
 //! ```
 //!
-//! This example fetches a playlist, sorts it by bandwidth and codecs, and then saves the sorted playlist to a file.
+//! This example fetches a playlist, sorts it by bandwidth and codecs, and then writes the sorted playlist out.
 //!
 //! ## Modules
 //!
 //! - `fetch: Provides functionality for fetching and parsing playlists from URLs or local files.
 //! - `sort`: Sorting functionalities for M3U8 master playlists by various criteria.
-//! - `parser`: Defines the structures and functions used for parsing M3U8 master playlists.
+//! - `parser`: Defines the structures and functions used for parsing M3U8 master and media playlists.
+//! - `codecs`: Decoding of RFC 6381 codec strings in CODECS attributes.
+//! - `steering`: Content steering pathway operations on master playlists.
+//! - `variables`: Substitution of EXT-X-DEFINE variables in master playlists.
+//! - `validate`: Checks of master playlists against the rules of RFC 8216 and Apple's HLS
+//!   Authoring Specification.
 //! - `errors`: Defines custom error types used throughout the library.
 
+pub mod align;
+pub mod codecs;
 pub mod errors;
 pub mod fetch;
 pub mod parser;
+pub mod profile;
 pub mod sort;
+pub mod steering;
+pub mod validate;
+pub mod variables;
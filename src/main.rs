@@ -1,69 +1,299 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Parser, Subcommand, ValueEnum};
 use m3u8_parse_sort::{
-    fetch::fetch_playlist,
-    sort::{get_sort_order, SortIFrameBy, SortMediaBy, SortStreamBy},
+    align::IFrameMatch,
+    codecs::VideoCodecFamily,
+    fetch::fetch_playlist_with_options,
+    parser::ParseOptions,
+    profile::SortProfile,
+    sort::{GroupOrder, Nulls, ResolutionOrder, SortIFrameBy, SortKey, SortMediaBy, SortStreamBy},
+    validate::{Profile, Severity, ValidationConfig},
 };
+use std::ffi::OsStr;
 use std::io::stdout;
-use tracing::{error, info};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::{error, info, warn};
 
 #[derive(Parser)]
 #[command(
     name = "HLS Playlist Sorter",
-    about = "Sort an HLS playlist from a URL or file"
+    about = "Sort an HLS playlist from a URL or file",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(
+        required = true,
         help = "The location of the playlist. Can be a file path or an HTTP URL.\nExamples:\n - /path/to/playlist.m3u8\n - http://example.com/playlist.m3u8"
     )]
-    pub playlist_location: String,
+    pub playlist_location: Option<String>,
 
     #[arg(
         short = 's',
         long,
-        value_enum,
+        value_parser = SortKeyParser::<SortStreamBy>::new(),
         value_delimiter = ',',
-        help = "Sort the #EXT-X-STREAM-INF elements by primary and secondary attributes (format: primary,secondary)"
+        default_value = "bandwidth",
+        help = "Sort the #EXT-X-STREAM-INF elements by attributes in order of precedence, each ascending unless followed by :desc, with missing values placed by --nulls unless followed by :nulls-first or :nulls-last (format: attribute[:asc|:desc][:nulls-first|:nulls-last],...)"
     )]
-    pub sort_stream_by: Vec<SortStreamBy>,
+    pub sort_stream_by: Vec<SortKey<SortStreamBy>>,
 
     #[arg(
         short = 'm',
         long,
-        value_enum,
+        value_parser = SortKeyParser::<SortMediaBy>::new(),
         value_delimiter = ',',
-        help = "Sort the #EXT-X-MEDIA elements by primary and secondary attributes (format: primary,secondary)"
+        default_value = "group-id",
+        help = "Sort the #EXT-X-MEDIA elements by attributes in order of precedence, each ascending unless followed by :desc, with missing values placed by --nulls unless followed by :nulls-first or :nulls-last (format: attribute[:asc|:desc][:nulls-first|:nulls-last],...)"
     )]
-    pub sort_media_by: Vec<SortMediaBy>,
+    pub sort_media_by: Vec<SortKey<SortMediaBy>>,
 
     #[arg(
         short = 'i',
+        long,
+        value_parser = SortKeyParser::<SortIFrameBy>::new(),
+        value_delimiter = ',',
+        default_value = "bandwidth",
+        help = "Sort the #EXT-X-I-FRAME-STREAM-INF elements by attributes in order of precedence, each ascending unless followed by :desc, with missing values placed by --nulls unless followed by :nulls-first or :nulls-last (format: attribute[:asc|:desc][:nulls-first|:nulls-last],...)"
+    )]
+    pub sort_iframe_by: Vec<SortKey<SortIFrameBy>>,
+
+    #[arg(
         long,
         value_enum,
         value_delimiter = ',',
-        help = "Sort the #EXT-X-I-FRAME-STREAM-INF elements by primary and secondary attributes (format: primary,secondary)"
+        help = "The order of video codec families when sorting by video-codec (format: first,second,...). Families not listed come after the listed ones"
+    )]
+    pub codec_order: Vec<VideoCodecFamily>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Nulls::First,
+        help = "Where elements missing the attribute of a sort key go, for the keys that do not set it themselves. A NaN frame-rate or score counts as missing"
+    )]
+    pub nulls: Nulls,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ResolutionOrder::WidthFirst,
+        help = "How the resolution sort keys compare resolutions"
+    )]
+    pub resolution_order: ResolutionOrder,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "The languages the language-preference media sort key puts first, most preferred first (format: en-US,en,es). A language such as es-MX also matches es. Unmatched languages keep their order after the matched ones"
+    )]
+    pub preferred_languages: Vec<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Keep the #EXT-X-MEDIA elements of each group together, ordering the groups by this and the elements inside each group by --sort-media-by"
+    )]
+    pub group_media_by: Option<GroupOrder>,
+
+    #[arg(
+        long,
+        requires = "group_media_by",
+        help = "Put the DEFAULT=YES rendition of each group first in it"
+    )]
+    pub pin_default: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Order the #EXT-X-I-FRAME-STREAM-INF elements to mirror the sorted #EXT-X-STREAM-INF elements, matching them by this. Elements that match the same stream keep the order of --sort-iframe-by, and the ones that match none go last. Warns of the streams of either kind left without a counterpart"
+    )]
+    pub align_iframes: Option<IFrameMatch>,
+
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "sort_stream_by", "sort_media_by", "sort_iframe_by", "codec_order", "nulls",
+            "resolution_order", "preferred_languages", "group_media_by", "pin_default",
+            "align_iframes",
+        ],
+        help = "A TOML or YAML file of named profiles, each describing the sort keys, options, filters and URI rewrites to apply in place of the sort flags"
+    )]
+    pub profile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "profile",
+        help = "The profile of --profile to apply. Defaults to the default profile of the file, or to its only profile"
+    )]
+    pub profile_name: Option<String>,
+
+    #[arg(
+        long,
+        help = "Fail on malformed values, missing required attributes and unknown enumerated values instead of recovering from them"
     )]
-    pub sort_iframe_by: Vec<SortIFrameBy>,
+    pub strict: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Check a playlist against the rules of RFC 8216 or another profile, exiting with a
+    /// non-zero status if any error is found
+    Lint {
+        #[arg(help = "The location of the playlist. Can be a file path or an HTTP URL.")]
+        playlist_location: String,
+
+        #[arg(
+            long,
+            help = "Fail on malformed values, missing required attributes and unknown enumerated values instead of recovering from them"
+        )]
+        strict: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "The set of rules to check against, overriding the profile of the configuration file"
+        )]
+        profile: Option<Profile>,
+
+        #[arg(
+            long,
+            help = "A TOML file selecting the profile and turning individual rules on or off"
+        )]
+        config: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// The profile the sort flags describe
+    fn sort_profile(&self) -> SortProfile {
+        SortProfile {
+            sort_stream_by: self.sort_stream_by.clone(),
+            sort_media_by: self.sort_media_by.clone(),
+            sort_iframe_by: self.sort_iframe_by.clone(),
+            codec_order: self.codec_order.clone(),
+            nulls: self.nulls,
+            resolution_order: self.resolution_order,
+            preferred_languages: self.preferred_languages.clone(),
+            group_media_by: self.group_media_by,
+            pin_default: self.pin_default,
+            align_iframes: self.align_iframes,
+            ..SortProfile::default()
+        }
+    }
+}
+
+/// Parses sort keys such as `bandwidth:desc`, listing the attributes of `T`
+/// as the possible values in the help.
+#[derive(Clone)]
+struct SortKeyParser<T>(PhantomData<T>);
+
+impl<T> SortKeyParser<T> {
+    fn new() -> Self {
+        SortKeyParser(PhantomData)
+    }
+}
+
+impl<T: ValueEnum + Clone + Send + Sync + 'static> TypedValueParser for SortKeyParser<T> {
+    type Value = SortKey<T>;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = value
+            .to_str()
+            .ok_or_else(|| clap::Error::new(ErrorKind::InvalidUtf8).with_cmd(cmd))?;
+        value.parse().map_err(|err: String| {
+            let arg = arg.map(|arg| arg.to_string()).unwrap_or_default();
+            clap::Error::raw(
+                ErrorKind::InvalidValue,
+                format!("invalid value '{}' for '{}': {}\n", value, arg, err),
+            )
+            .with_cmd(cmd)
+        })
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            T::value_variants()
+                .iter()
+                .filter_map(|value| value.to_possible_value()),
+        ))
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
     let args = Cli::parse();
 
-    info!("Fetching playlist from {}", &args.playlist_location);
+    match args.command {
+        Some(Command::Lint {
+            ref playlist_location,
+            strict,
+            profile,
+            ref config,
+        }) => {
+            let mut config = match config {
+                Some(path) => ValidationConfig::load(path)?,
+                None => ValidationConfig::default(),
+            };
+            if let Some(profile) = profile {
+                config.profile = profile;
+            }
+            lint(playlist_location, ParseOptions { strict }, &config).await
+        }
+        None => sort(&args).await.map(|_| ExitCode::SUCCESS),
+    }
+}
 
-    match fetch_playlist(&args.playlist_location).await {
-        Ok(mut playlist) => {
+/// Sorts the playlist and writes it to stdout
+async fn sort(args: &Cli) -> Result<()> {
+    let location = args
+        .playlist_location
+        .as_deref()
+        .expect("The playlist location is required");
+
+    info!("Fetching playlist from {}", location);
+
+    let profile = match args.profile {
+        Some(ref path) => SortProfile::load(path, args.profile_name.as_deref())?,
+        None => args.sort_profile(),
+    };
+
+    let options = ParseOptions {
+        strict: args.strict,
+    };
+
+    match fetch_playlist_with_options(location, options).await {
+        Ok((mut playlist, diagnostics)) => {
             info!("Successfully fetched and parsed playlist.");
+            for diagnostic in &diagnostics {
+                warn!("Recovered from a malformed playlist at {}", diagnostic);
+            }
 
             // Sort the playlist based on the selected sorting criteria
-            playlist.sort_stream(get_sort_order(&args.sort_stream_by));
-            playlist.sort_media(get_sort_order(&args.sort_media_by));
-            playlist.sort_iframe(get_sort_order(&args.sort_iframe_by));
+            if let Some(alignment) = playlist.apply_profile(&profile) {
+                for uri in &alignment.unmatched_variants {
+                    warn!("No I-frame stream matches the variant stream {}", uri);
+                }
+                for uri in &alignment.unmatched_frames {
+                    warn!("The I-frame stream {} matches no variant stream", uri);
+                }
+            }
 
             // Write the sorted playlist to stdout
             let stdout = stdout();
@@ -74,10 +304,51 @@ async fn main() -> Result<()> {
             info!("Playlist successfully written to output.");
         }
         Err(err) => {
-            error!("Failed to fetch or parse playlist: {:?}", err);
+            error!("Failed to fetch or parse playlist: {}", err);
             return Err(err.into());
         }
     }
 
     Ok(())
 }
+
+/// Prints the findings of the validator and the recoveries made while
+/// parsing. Fails if any finding is an error.
+async fn lint(
+    location: &str,
+    options: ParseOptions,
+    config: &ValidationConfig,
+) -> Result<ExitCode> {
+    let (playlist, diagnostics) = match fetch_playlist_with_options(location, options).await {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            error!("Failed to fetch or parse playlist: {}", err);
+            return Err(err.into());
+        }
+    };
+
+    for diagnostic in &diagnostics {
+        println!("warning[parse] {}", diagnostic);
+    }
+
+    let findings = playlist.validate_with_config(config);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    println!(
+        "{} errors, {} warnings",
+        errors,
+        findings.len() - errors + diagnostics.len()
+    );
+
+    Ok(if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
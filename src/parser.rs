@@ -1,33 +1,80 @@
-//! This file defines the structure and functionality for parsing and managing M3U8 master playlists.
-//! It provides parsing utilities for various tags, including stream variants, media tracks,
-//! and I-frame streams, and allows serializing these structures back to a playlist format.
+//! This file defines the structure and functionality for parsing and managing M3U8 playlists.
+//! It provides parsing utilities for the master playlist tags, including stream variants, media
+//! tracks, and I-frame streams, as well as for media playlists and their segments, and allows
+//! serializing these structures back to a playlist format.
 //!
 //! For more detailed documentation on the playlist format and the tags used, refer to:
 //! https://datatracker.ietf.org/doc/html/rfc8216
 
-use crate::errors::PlaylistError;
+use crate::errors::{Diagnostic, ParseError, PlaylistError};
+use crate::variables::{variable_name, variable_references};
 use nom::{
     bytes::complete::tag,
-    character::complete::{line_ending, multispace0, not_line_ending},
-    combinator::opt,
-    multi::separated_list1,
+    character::complete::{line_ending, multispace0, not_line_ending, space0},
+    combinator::{map, opt},
     sequence::separated_pair,
-    IResult,
+    IResult, Offset,
+};
+use nom::{
+    error::{ErrorKind, ParseError as NomParseError},
+    Err as NomErr,
 };
-use nom::{error::Error as NomError, Err as NomErr};
 use std::{
+    collections::HashSet,
     fmt,
     io::{Result as IoResult, Write},
+    str::FromStr,
 };
 
 /// The Master Playlist defines the Variant Streams, Renditions, and
 /// other global parameters of the presentation.
-#[derive(Debug)]
+///
+/// `items` records the lines of the parsed playlist in their original
+/// order, so that unknown tags, comments and blank lines are written back
+/// out where they were found, with the original `line_endings`. It is empty
+/// for playlists built by hand, which are written using the default layout.
+#[derive(Debug, Clone)]
 pub struct MasterPlaylist {
+    pub version: Option<u32>,
     pub independent_segments: bool,
+    pub start: Option<Start>,
+    pub defines: Vec<Define>,
+    pub content_steering: Option<ContentSteering>,
+    pub session_data: Vec<SessionData>,
+    pub session_keys: Vec<SessionKey>,
     pub variants: Vec<StreamVariant>,
     pub media: Vec<MediaTrack>,
     pub frames: Vec<IFrameStream>,
+    pub items: Vec<PlaylistItem>,
+    pub line_endings: LineEndings,
+}
+
+/// A line of a master playlist, in the order it appeared in the source.
+///
+/// The known elements are placeholders, one per parsed element. While the
+/// elements of a kind are in their parsed order, each one is written at its
+/// own placeholder. Once a sort has moved them, the whole collection of the
+/// kind is written at its first placeholder, so that it stays in one block,
+/// while the unknown tags and comments stay put.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaylistItem {
+    Version,
+    IndependentSegments,
+    Start,
+    Define,
+    ContentSteering,
+    SessionData,
+    SessionKey,
+    Media,
+    Variant,
+    IFrame,
+    /// A tag the parser does not model, kept verbatim.
+    Tag(String),
+    /// A comment line, kept verbatim.
+    Comment(String),
+    /// Any other line that is not part of a known element, kept verbatim.
+    Other(String),
+    Blank,
 }
 
 /// The EXT-X-STREAM-INF tag specifies a Variant Stream, which is a set
@@ -36,17 +83,41 @@ pub struct MasterPlaylist {
 ///
 /// The URI line specifies a Media Playlist that carries a Rendition of
 /// the Variant Stream.  
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StreamVariant {
-    pub bandwidth: u32,
-    pub average_bandwidth: Option<u32>,
+    pub program_id: Option<u32>,
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub score: Option<f32>,
     pub codecs: Option<String>,
+    pub supplemental_codecs: Option<String>,
     pub resolution: Option<(u32, u32)>,
     pub frame_rate: Option<f32>,
-    pub video_range: Option<String>,
+    pub hdcp_level: Option<HdcpLevel>,
+    pub allowed_cpc: Option<String>,
+    pub video_range: Option<VideoRange>,
+    pub req_video_layout: Option<String>,
+    pub stable_variant_id: Option<String>,
     pub audio: Option<String>,
-    pub closed_captions: Option<String>,
+    pub video: Option<String>,
+    pub subtitles: Option<String>,
+    pub closed_captions: Option<ClosedCaptions>,
+    pub pathway_id: Option<String>,
     pub uri: String,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The HDCP-LEVEL attribute indicates the level of High-bandwidth Digital
+/// Content Protection that the Variant Stream requires.  Levels are ordered
+/// from least to most restrictive.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum HdcpLevel {
+    None,
+    Type0,
+    Type1,
+    /// A value not defined by the specification, kept verbatim.
+    Other(String),
 }
 
 /// The EXT-X-MEDIA tag is used to relate Media Playlists that contain
@@ -55,48 +126,609 @@ pub struct StreamVariant {
 /// that contain English, French, and Spanish Renditions of the same
 /// presentation.  Or, two EXT-X-MEDIA tags can be used to identify
 /// video-only Media Playlists that show two different camera angles.
-#[derive(Debug)]
+///
+#[derive(Debug, Clone)]
 pub struct MediaTrack {
-    pub track_type: Option<String>,
-    pub group_id: Option<String>,
-    pub name: Option<String>,
+    pub track_type: MediaType,
+    pub group_id: String,
+    pub name: String,
     pub language: Option<String>,
-    pub default: Option<String>,
-    pub autoselect: Option<String>,
+    pub assoc_language: Option<String>,
+    pub stable_rendition_id: Option<String>,
+    pub default: Option<bool>,
+    pub autoselect: Option<bool>,
+    pub forced: Option<bool>,
+    pub instream_id: Option<String>,
+    pub bit_depth: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub characteristics: Option<String>,
     pub channels: Option<String>,
     pub uri: Option<String>,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IFrameStream {
-    pub bandwidth: u32,
+    pub bandwidth: u64,
     pub codecs: Option<String>,
     pub resolution: Option<(u32, u32)>,
-    pub video_range: Option<String>,
+    pub video_range: Option<VideoRange>,
+    pub pathway_id: Option<String>,
     pub uri: String,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
 }
 
-impl fmt::Display for MediaTrack {
+/// The EXT-X-START tag indicates a preferred point at which to start
+/// playing a Playlist.
+#[derive(Debug, Clone)]
+pub struct Start {
+    pub time_offset: f32,
+    pub precise: Option<bool>,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The EXT-X-DEFINE tag provides a Playlist variable definition or
+/// declaration.  Variable references of the form `{$name}` in URI lines
+/// and quoted-string attribute values are kept as written; see
+/// `MasterPlaylist::resolve_variables` for the substituted view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Define {
+    pub definition: Definition,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The form of an EXT-X-DEFINE tag, set by which of its attributes are
+/// present.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Definition {
+    /// Defines a variable with the given NAME and VALUE.
+    Value { name: String, value: String },
+    /// Imports a variable from the Multivariant Playlist.
+    Import(String),
+    /// Takes the value of the named query parameter of the Playlist URI.
+    QueryParam(String),
+}
+
+/// The EXT-X-CONTENT-STEERING tag allows a server to provide a Content
+/// Steering Manifest, which the client uses to choose between the Pathways
+/// identified by the PATHWAY-ID attribute of each Variant Stream.
+#[derive(Debug, Clone)]
+pub struct ContentSteering {
+    pub server_uri: String,
+    pub pathway_id: Option<String>,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The EXT-X-SESSION-DATA tag allows arbitrary session data to be
+/// carried in a Master Playlist.
+#[derive(Debug, Clone)]
+pub struct SessionData {
+    pub data_id: String,
+    pub value: Option<String>,
+    pub uri: Option<String>,
+    pub format: Option<SessionDataFormat>,
+    pub language: Option<String>,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The FORMAT attribute of an EXT-X-SESSION-DATA tag.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SessionDataFormat {
+    Json,
+    Raw,
+}
+
+/// The EXT-X-SESSION-KEY tag allows encryption keys from Media Playlists
+/// to be specified in a Master Playlist.  This allows the client to
+/// preload these keys without having to read the Media Playlist(s) first.
+#[derive(Debug, Clone)]
+pub struct SessionKey {
+    pub method: KeyMethod,
+    pub uri: Option<String>,
+    pub iv: Option<String>,
+    pub keyformat: Option<String>,
+    pub keyformatversions: Option<String>,
+    pub source: Source,
+    pub extra_attributes: Vec<ExtraAttribute>,
+}
+
+/// The METHOD attribute of an EXT-X-SESSION-KEY tag.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KeyMethod {
+    None,
+    Aes128,
+    SampleAes,
+    SampleAesCtr,
+    /// A value not defined by the specification, kept verbatim.
+    Other(String),
+}
+
+/// The VIDEO-RANGE attribute specifies the dynamic range of the video in
+/// the Variant Stream.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum VideoRange {
+    Sdr,
+    Hlg,
+    Pq,
+    /// A value not defined by the specification, kept verbatim.
+    Other(String),
+}
+
+/// The CLOSED-CAPTIONS attribute of a Variant Stream is either the GROUP-ID
+/// of a set of closed-caption Renditions, or the enumerated value NONE when
+/// the Variant Stream carries no closed captions.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ClosedCaptions {
+    None,
+    GroupId(String),
+}
+
+/// The TYPE attribute of an EXT-X-MEDIA tag.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MediaType {
+    Audio,
+    Video,
+    Subtitles,
+    ClosedCaptions,
+}
+
+/// The line endings of a parsed playlist, kept so that it is written back
+/// with the same ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndings {
+    /// Whether the lines end with CR LF rather than LF
+    pub crlf: bool,
+    /// Whether the last line has no line ending
+    pub unterminated: bool,
+}
+
+impl LineEndings {
+    /// The line endings of a playlist, as those of its first line
+    fn of(input: &str) -> Self {
+        LineEndings {
+            crlf: input
+                .find('\n')
+                .is_some_and(|end| input[..end].ends_with('\r')),
+            unterminated: !input.trim_end_matches([' ', '\t']).ends_with('\n'),
+        }
+    }
+
+    /// Writes `lines`, written with LF line endings, with these line endings
+    fn write<T: Write>(self, w: &mut T, lines: &[u8]) -> IoResult<()> {
+        let lines = match lines.strip_suffix(b"\n") {
+            Some(last_line_ending) if self.unterminated => last_line_ending,
+            _ => lines,
+        };
+        if !self.crlf {
+            return w.write_all(lines);
+        }
+        for line in lines.split_inclusive(|&byte| byte == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    w.write_all(line)?;
+                    w.write_all(b"\r\n")?;
+                }
+                None => w.write_all(line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where an element came from in the parsed playlist, kept so that it is
+/// written back the way it was. Elements built by hand have an empty
+/// source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    /// The position of the element among the parsed elements of its kind,
+    /// which is also the position of its placeholder among the placeholders
+    /// of its kind in `items`
+    pub index: Option<usize>,
+    /// The names and values of the attributes, in the order they were
+    /// written, with the quotes removed from the values. The duration of an
+    /// EXTINF tag is recorded as a `DURATION` attribute.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Source {
+    /// Formats a decimal attribute with the text it was written with, such
+    /// as `30.000`, as long as that text still stands for `value`
+    fn decimal<N>(&self, name: &str, value: N) -> String
+    where
+        N: FromStr + PartialEq + Copy + fmt::Display,
+    {
+        self.attributes
+            .iter()
+            .find(|(written, _)| written == name)
+            .map(|(_, text)| text)
+            .filter(|text| text.parse::<N>().ok() == Some(value))
+            .cloned()
+            .unwrap_or_else(|| value.to_string())
+    }
+}
+
+/// An attribute that has no dedicated field on the struct of its tag. It is
+/// kept so that it can be written back out unchanged, at `position` in the
+/// attribute list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraAttribute {
+    pub name: String,
+    pub value: String,
+    pub quoted: bool,
+    pub position: usize,
+}
+
+/// A Media Playlist contains a list of Media Segments, which, when played
+/// sequentially, will play the multimedia presentation.
+#[derive(Debug)]
+pub struct MediaPlaylist {
+    pub version: Option<u32>,
+    pub target_duration: Option<u64>,
+    pub media_sequence: Option<u64>,
+    pub playlist_type: Option<PlaylistType>,
+    pub independent_segments: bool,
+    pub end_list: bool,
+    pub segments: Vec<MediaSegment>,
+    pub items: Vec<MediaPlaylistItem>,
+    pub line_endings: LineEndings,
+}
+
+/// A line of a media playlist, in the order it appeared in the source.
+///
+/// The known tags are placeholders for the fields of `MediaPlaylist`, and
+/// there is a `Segment` placeholder per parsed segment, filled as described
+/// on `PlaylistItem`. The tags the parser does not model, such as EXT-X-KEY,
+/// EXT-X-MAP or EXT-X-DISCONTINUITY, are kept verbatim before the segment
+/// they apply to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaPlaylistItem {
+    Version,
+    TargetDuration,
+    MediaSequence,
+    PlaylistType,
+    IndependentSegments,
+    Segment,
+    EndList,
+    /// A tag the parser does not model, kept verbatim.
+    Tag(String),
+    /// A comment line, kept verbatim.
+    Comment(String),
+    /// A URI line with no EXTINF tag before it, kept verbatim.
+    Other(String),
+    Blank,
+}
+
+/// The EXT-X-PLAYLIST-TYPE tag provides mutability information about the
+/// Media Playlist.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlaylistType {
+    Event,
+    Vod,
+}
+
+/// A Media Segment is specified by a URI and optionally the media
+/// segment tags that apply to it.  The EXTINF tag specifies the duration
+/// of the Media Segment and an optional human-readable title.
+#[derive(Debug)]
+pub struct MediaSegment {
+    pub duration: f64,
+    pub title: Option<String>,
+    pub uri: String,
+    pub source: Source,
+}
+
+/// A playlist of either kind, as detected from its contents.
+#[derive(Debug)]
+pub enum Playlist {
+    Master(Box<MasterPlaylist>),
+    Media(MediaPlaylist),
+}
+
+impl From<String> for HdcpLevel {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "NONE" => HdcpLevel::None,
+            "TYPE-0" => HdcpLevel::Type0,
+            "TYPE-1" => HdcpLevel::Type1,
+            _ => HdcpLevel::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for HdcpLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdcpLevel::None => write!(f, "NONE"),
+            HdcpLevel::Type0 => write!(f, "TYPE-0"),
+            HdcpLevel::Type1 => write!(f, "TYPE-1"),
+            HdcpLevel::Other(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl StreamVariant {
+    /// The rendition groups this variant stream refers to, as pairs of the
+    /// media type and GROUP-ID.
+    pub fn group_references(&self) -> Vec<(MediaType, &str)> {
+        let closed_captions = match self.closed_captions {
+            Some(ClosedCaptions::GroupId(ref group_id)) => Some(group_id),
+            _ => None,
+        };
+        [
+            (MediaType::Audio, self.audio.as_ref()),
+            (MediaType::Video, self.video.as_ref()),
+            (MediaType::Subtitles, self.subtitles.as_ref()),
+            (MediaType::ClosedCaptions, closed_captions),
+        ]
+        .into_iter()
+        .filter_map(|(media_type, group_id)| group_id.map(|id| (media_type, id.as_str())))
+        .collect()
+    }
+}
+
+impl From<String> for KeyMethod {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "NONE" => KeyMethod::None,
+            "AES-128" => KeyMethod::Aes128,
+            "SAMPLE-AES" => KeyMethod::SampleAes,
+            "SAMPLE-AES-CTR" => KeyMethod::SampleAesCtr,
+            _ => KeyMethod::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for KeyMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyMethod::None => write!(f, "NONE"),
+            KeyMethod::Aes128 => write!(f, "AES-128"),
+            KeyMethod::SampleAes => write!(f, "SAMPLE-AES"),
+            KeyMethod::SampleAesCtr => write!(f, "SAMPLE-AES-CTR"),
+            KeyMethod::Other(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for SessionDataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionDataFormat::Json => write!(f, "JSON"),
+            SessionDataFormat::Raw => write!(f, "RAW"),
+        }
+    }
+}
+
+impl From<String> for VideoRange {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "SDR" => VideoRange::Sdr,
+            "HLG" => VideoRange::Hlg,
+            "PQ" => VideoRange::Pq,
+            _ => VideoRange::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for VideoRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoRange::Sdr => write!(f, "SDR"),
+            VideoRange::Hlg => write!(f, "HLG"),
+            VideoRange::Pq => write!(f, "PQ"),
+            VideoRange::Other(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for ClosedCaptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClosedCaptions::None => write!(f, "NONE"),
+            ClosedCaptions::GroupId(group_id) => write!(f, "\"{}\"", group_id),
+        }
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaType::Audio => write!(f, "AUDIO"),
+            MediaType::Video => write!(f, "VIDEO"),
+            MediaType::Subtitles => write!(f, "SUBTITLES"),
+            MediaType::ClosedCaptions => write!(f, "CLOSED-CAPTIONS"),
+        }
+    }
+}
+
+/// Formats a boolean as a YES/NO enumerated-string
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "YES"
+    } else {
+        "NO"
+    }
+}
+
+impl fmt::Display for ExtraAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.quoted {
+            write!(f, "{}=\"{}\"", self.name, self.value)
+        } else {
+            write!(f, "{}={}", self.name, self.value)
+        }
+    }
+}
+
+/// Puts the formatted attribute list back in the order the attributes were
+/// written in, then inserts the extra attributes at their original
+/// positions. Attributes missing from the source keep their order, after
+/// the others.
+fn arrange_attributes(
+    parts: &mut Vec<String>,
+    source: &Source,
+    extra_attributes: &[ExtraAttribute],
+) {
+    parts.sort_by_key(|part| {
+        let name = part.split('=').next().unwrap_or_default();
+        source
+            .attributes
+            .iter()
+            .position(|(written, _)| written == name)
+            .unwrap_or(usize::MAX)
+    });
+    for attribute in extra_attributes {
+        let index = attribute.position.min(parts.len());
+        parts.insert(index, attribute.to_string());
+    }
+}
+
+impl fmt::Display for Start {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        // Mandatory field
+        parts.push(format!(
+            "TIME-OFFSET={}",
+            self.source.decimal("TIME-OFFSET", self.time_offset)
+        ));
+
+        if let Some(precise) = self.precise {
+            parts.push(format!("PRECISE={}", yes_no(precise)));
+        }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
+
+        write!(f, "#EXT-X-START:{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for Define {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = match self.definition {
+            Definition::Value {
+                ref name,
+                ref value,
+            } => vec![format!("NAME=\"{}\"", name), format!("VALUE=\"{}\"", value)],
+            Definition::Import(ref name) => vec![format!("IMPORT=\"{}\"", name)],
+            Definition::QueryParam(ref name) => vec![format!("QUERYPARAM=\"{}\"", name)],
+        };
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
+
+        write!(f, "#EXT-X-DEFINE:{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for ContentSteering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        // Mandatory field
+        parts.push(format!("SERVER-URI=\"{}\"", self.server_uri));
+
+        if let Some(ref pathway_id) = self.pathway_id {
+            parts.push(format!("PATHWAY-ID=\"{}\"", pathway_id));
+        }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
+
+        write!(f, "#EXT-X-CONTENT-STEERING:{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for SessionData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        // Mandatory field
+        parts.push(format!("DATA-ID=\"{}\"", self.data_id));
+
+        // Optional fields
+        if let Some(ref value) = self.value {
+            parts.push(format!("VALUE=\"{}\"", value));
+        }
+        if let Some(ref uri) = self.uri {
+            parts.push(format!("URI=\"{}\"", uri));
+        }
+        if let Some(format) = self.format {
+            parts.push(format!("FORMAT={}", format));
+        }
+        if let Some(ref language) = self.language {
+            parts.push(format!("LANGUAGE=\"{}\"", language));
+        }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
+
+        write!(f, "#EXT-X-SESSION-DATA:{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for SessionKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut parts = Vec::new();
 
-        if let Some(ref track_type) = self.track_type {
-            parts.push(format!("TYPE={}", track_type));
+        // Mandatory field
+        parts.push(format!("METHOD={}", self.method));
+
+        // Optional fields
+        if let Some(ref uri) = self.uri {
+            parts.push(format!("URI=\"{}\"", uri));
         }
-        if let Some(ref group_id) = self.group_id {
-            parts.push(format!("GROUP-ID=\"{}\"", group_id));
+        if let Some(ref iv) = self.iv {
+            parts.push(format!("IV={}", iv));
         }
-        if let Some(ref name) = self.name {
-            parts.push(format!("NAME=\"{}\"", name));
+        if let Some(ref keyformat) = self.keyformat {
+            parts.push(format!("KEYFORMAT=\"{}\"", keyformat));
         }
+        if let Some(ref keyformatversions) = self.keyformatversions {
+            parts.push(format!("KEYFORMATVERSIONS=\"{}\"", keyformatversions));
+        }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
+
+        write!(f, "#EXT-X-SESSION-KEY:{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for MediaTrack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        // Mandatory fields: TYPE, GROUP-ID and NAME
+        parts.push(format!("TYPE={}", self.track_type));
+        parts.push(format!("GROUP-ID=\"{}\"", self.group_id));
+        parts.push(format!("NAME=\"{}\"", self.name));
         if let Some(ref language) = self.language {
             parts.push(format!("LANGUAGE=\"{}\"", language));
         }
-        if let Some(ref default) = self.default {
-            parts.push(format!("DEFAULT={}", default));
+        if let Some(ref assoc_language) = self.assoc_language {
+            parts.push(format!("ASSOC-LANGUAGE=\"{}\"", assoc_language));
+        }
+        if let Some(ref stable_rendition_id) = self.stable_rendition_id {
+            parts.push(format!("STABLE-RENDITION-ID=\"{}\"", stable_rendition_id));
         }
-        if let Some(ref autoselect) = self.autoselect {
-            parts.push(format!("AUTOSELECT={}", autoselect));
+        if let Some(default) = self.default {
+            parts.push(format!("DEFAULT={}", yes_no(default)));
+        }
+        if let Some(autoselect) = self.autoselect {
+            parts.push(format!("AUTOSELECT={}", yes_no(autoselect)));
+        }
+        if let Some(forced) = self.forced {
+            parts.push(format!("FORCED={}", yes_no(forced)));
+        }
+        if let Some(ref instream_id) = self.instream_id {
+            parts.push(format!("INSTREAM-ID=\"{}\"", instream_id));
+        }
+        if let Some(bit_depth) = self.bit_depth {
+            parts.push(format!("BIT-DEPTH={}", bit_depth));
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            parts.push(format!("SAMPLE-RATE={}", sample_rate));
+        }
+        if let Some(ref characteristics) = self.characteristics {
+            parts.push(format!("CHARACTERISTICS=\"{}\"", characteristics));
         }
         if let Some(ref channels) = self.channels {
             parts.push(format!("CHANNELS=\"{}\"", channels));
@@ -104,6 +736,7 @@ impl fmt::Display for MediaTrack {
         if let Some(ref uri) = self.uri {
             parts.push(format!("URI=\"{}\"", uri));
         }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
 
         write!(f, "#EXT-X-MEDIA:{}", parts.join(","))
     }
@@ -113,6 +746,11 @@ impl fmt::Display for StreamVariant {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut parts = Vec::new();
 
+        // Removed in protocol version 6, but still written first when present
+        if let Some(program_id) = self.program_id {
+            parts.push(format!("PROGRAM-ID={}", program_id));
+        }
+
         // Mandatory field
         parts.push(format!("BANDWIDTH={}", self.bandwidth));
 
@@ -120,24 +758,55 @@ impl fmt::Display for StreamVariant {
         if let Some(average_bandwidth) = self.average_bandwidth {
             parts.push(format!("AVERAGE-BANDWIDTH={}", average_bandwidth));
         }
+        if let Some(score) = self.score {
+            parts.push(format!("SCORE={}", self.source.decimal("SCORE", score)));
+        }
         if let Some(ref codecs) = self.codecs {
             parts.push(format!("CODECS=\"{}\"", codecs));
         }
+        if let Some(ref supplemental_codecs) = self.supplemental_codecs {
+            parts.push(format!("SUPPLEMENTAL-CODECS=\"{}\"", supplemental_codecs));
+        }
         if let Some((width, height)) = self.resolution {
             parts.push(format!("RESOLUTION={}x{}", width, height));
         }
         if let Some(frame_rate) = self.frame_rate {
-            parts.push(format!("FRAME-RATE={}", frame_rate));
+            parts.push(format!(
+                "FRAME-RATE={}",
+                self.source.decimal("FRAME-RATE", frame_rate)
+            ));
+        }
+        if let Some(ref hdcp_level) = self.hdcp_level {
+            parts.push(format!("HDCP-LEVEL={}", hdcp_level));
+        }
+        if let Some(ref allowed_cpc) = self.allowed_cpc {
+            parts.push(format!("ALLOWED-CPC=\"{}\"", allowed_cpc));
         }
         if let Some(ref video_range) = self.video_range {
             parts.push(format!("VIDEO-RANGE={}", video_range));
         }
+        if let Some(ref req_video_layout) = self.req_video_layout {
+            parts.push(format!("REQ-VIDEO-LAYOUT=\"{}\"", req_video_layout));
+        }
+        if let Some(ref stable_variant_id) = self.stable_variant_id {
+            parts.push(format!("STABLE-VARIANT-ID=\"{}\"", stable_variant_id));
+        }
         if let Some(ref audio) = self.audio {
             parts.push(format!("AUDIO=\"{}\"", audio));
         }
+        if let Some(ref video) = self.video {
+            parts.push(format!("VIDEO=\"{}\"", video));
+        }
+        if let Some(ref subtitles) = self.subtitles {
+            parts.push(format!("SUBTITLES=\"{}\"", subtitles));
+        }
         if let Some(ref closed_captions) = self.closed_captions {
             parts.push(format!("CLOSED-CAPTIONS={}", closed_captions));
         }
+        if let Some(ref pathway_id) = self.pathway_id {
+            parts.push(format!("PATHWAY-ID=\"{}\"", pathway_id));
+        }
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
 
         // Write the #EXT-X-STREAM-INF line
         write!(f, "#EXT-X-STREAM-INF:{}\n{}", parts.join(","), self.uri)
@@ -161,22 +830,158 @@ impl fmt::Display for IFrameStream {
         if let Some(ref video_range) = self.video_range {
             parts.push(format!("VIDEO-RANGE={}", video_range));
         }
+        if let Some(ref pathway_id) = self.pathway_id {
+            parts.push(format!("PATHWAY-ID=\"{}\"", pathway_id));
+        }
 
         // URI field (always present)
         parts.push(format!("URI=\"{}\"", self.uri));
+        arrange_attributes(&mut parts, &self.source, &self.extra_attributes);
 
         // Write the formatted string to the output
         write!(f, "#EXT-X-I-FRAME-STREAM-INF:{}", parts.join(","))
     }
 }
 
+impl fmt::Display for PlaylistType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaylistType::Event => write!(f, "EVENT"),
+            PlaylistType::Vod => write!(f, "VOD"),
+        }
+    }
+}
+
+impl fmt::Display for MediaSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#EXTINF:{},{}\n{}",
+            self.source.decimal("DURATION", self.duration),
+            self.title.as_deref().unwrap_or(""),
+            self.uri
+        )
+    }
+}
+
 impl MasterPlaylist {
     /// Writes the MasterPlaylist to any `Write` type (e.g., file, buffer)
     pub fn write_to<T: Write>(&self, w: &mut T) -> IoResult<()> {
-        writeln!(w, "#EXTM3U")?;
+        let mut lines = Vec::new();
+        writeln!(lines, "#EXTM3U")?;
 
-        if self.independent_segments {
-            writeln!(w, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+        if self.items.is_empty() {
+            self.write_default_layout(&mut lines)?;
+        } else {
+            self.write_items(&mut lines)?;
+        }
+        self.line_endings.write(w, &lines)
+    }
+
+    /// Writes the elements in the order recorded in `items`, as described on
+    /// `PlaylistItem`. Unknown tags, comments, other lines and blank lines
+    /// stay in place.
+    fn write_items<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        let has = |kind: PlaylistItem| self.items.contains(&kind);
+        let mut defines = Placement::new(&self.defines, |define| define.source.index);
+        let mut session_data = Placement::new(&self.session_data, |data| data.source.index);
+        let mut session_keys = Placement::new(&self.session_keys, |key| key.source.index);
+        let mut media = Placement::new(&self.media, |track| track.source.index);
+        let mut variants = Placement::new(&self.variants, |variant| variant.source.index);
+        let mut frames = Placement::new(&self.frames, |frame| frame.source.index);
+
+        // Playlist-wide tags that had no placeholder are written right after #EXTM3U
+        if !has(PlaylistItem::Version) {
+            self.write_version(w)?;
+        }
+        if !has(PlaylistItem::IndependentSegments) {
+            self.write_independent_segments(w)?;
+        }
+        if !has(PlaylistItem::Start) {
+            self.write_start(w)?;
+        }
+        if !has(PlaylistItem::ContentSteering) {
+            self.write_content_steering(w)?;
+        }
+        if !has(PlaylistItem::Define) {
+            write_all(w, defines.take_rest())?;
+        }
+        if !has(PlaylistItem::SessionData) {
+            write_all(w, session_data.take_rest())?;
+        }
+        if !has(PlaylistItem::SessionKey) {
+            write_all(w, session_keys.take_rest())?;
+        }
+
+        let mut blank_lines = BlankLines::default();
+        let mut seen = Vec::new();
+        for item in &self.items {
+            match item {
+                PlaylistItem::Version
+                | PlaylistItem::IndependentSegments
+                | PlaylistItem::Start
+                | PlaylistItem::ContentSteering => {
+                    // A tag that may only appear once is written at its first placeholder
+                    if seen.contains(&item) {
+                        blank_lines.emptied = true;
+                        continue;
+                    }
+                    seen.push(item);
+                }
+                PlaylistItem::Blank => {
+                    blank_lines.count += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut line = Vec::new();
+            match item {
+                PlaylistItem::Version => self.write_version(&mut line)?,
+                PlaylistItem::IndependentSegments => self.write_independent_segments(&mut line)?,
+                PlaylistItem::Start => self.write_start(&mut line)?,
+                PlaylistItem::ContentSteering => self.write_content_steering(&mut line)?,
+                PlaylistItem::Define => write_all(&mut line, defines.next_placeholder())?,
+                PlaylistItem::SessionData => write_all(&mut line, session_data.next_placeholder())?,
+                PlaylistItem::SessionKey => write_all(&mut line, session_keys.next_placeholder())?,
+                PlaylistItem::Media => write_all(&mut line, media.next_placeholder())?,
+                PlaylistItem::Variant => write_all(&mut line, variants.next_placeholder())?,
+                PlaylistItem::IFrame => write_all(&mut line, frames.next_placeholder())?,
+                PlaylistItem::Tag(text)
+                | PlaylistItem::Comment(text)
+                | PlaylistItem::Other(text) => writeln!(line, "{}", text)?,
+                PlaylistItem::Blank => unreachable!("Blank lines are counted above"),
+            }
+            blank_lines.write_before(w, &line)?;
+        }
+        blank_lines.write_waiting(w)?;
+
+        // Elements that had no placeholder left go at the end
+        write_all(w, defines.take_rest())?;
+        write_all(w, session_data.take_rest())?;
+        write_all(w, session_keys.take_rest())?;
+        write_all(w, media.take_rest())?;
+        write_all(w, variants.take_rest())?;
+        write_all(w, frames.take_rest())?;
+
+        Ok(())
+    }
+
+    /// Writes the playlist-wide tags followed by the elements grouped as media tracks,
+    /// stream variants and I-frame streams
+    fn write_default_layout<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        self.write_version(w)?;
+        self.write_independent_segments(w)?;
+        self.write_start(w)?;
+        self.write_content_steering(w)?;
+        for define in &self.defines {
+            writeln!(w, "{}", define)?;
+        }
+        for session_data in &self.session_data {
+            writeln!(w, "{}", session_data)?;
+        }
+        for session_key in &self.session_keys {
+            writeln!(w, "{}", session_key)?;
         }
         writeln!(w)?;
 
@@ -199,183 +1004,1126 @@ impl MasterPlaylist {
 
         Ok(())
     }
-}
 
-/// Main function to parse the entire M3U8 playlist
-pub fn parse_playlist(input: &str) -> Result<MasterPlaylist, PlaylistError> {
-    let (_, playlist) = parse_master_playlist(input).map_err(|e| match e {
-        NomErr::Incomplete(needed) => {
-            PlaylistError::Incomplete(format!("Incomplete input, needed: {:?}", needed))
+    fn write_version<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        if let Some(version) = self.version {
+            writeln!(w, "#EXT-X-VERSION:{}", version)?;
         }
-        NomErr::Error(NomError { input, code }) | NomErr::Failure(NomError { input, code }) => {
-            PlaylistError::ParseError(NomErr::Error(NomError {
-                input: input.to_string(),
-                code,
-            }))
+        Ok(())
+    }
+
+    fn write_independent_segments<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        if self.independent_segments {
+            writeln!(w, "#EXT-X-INDEPENDENT-SEGMENTS")?;
         }
-    })?;
+        Ok(())
+    }
+
+    fn write_start<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        if let Some(ref start) = self.start {
+            writeln!(w, "{}", start)?;
+        }
+        Ok(())
+    }
+
+    fn write_content_steering<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        if let Some(ref content_steering) = self.content_steering {
+            writeln!(w, "{}", content_steering)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the elements of one kind are written. While the elements are in
+/// their parsed order, each one is written at its own placeholder, followed
+/// by the elements added after it. Once a sort has moved them, they are all
+/// written at the first placeholder of their kind.
+struct Placement<'a, T> {
+    elements: &'a [T],
+    index: fn(&T) -> Option<usize>,
+    in_place: bool,
+    placeholders: usize,
+    next: usize,
+}
+
+impl<'a, T> Placement<'a, T> {
+    fn new(elements: &'a [T], index: fn(&T) -> Option<usize>) -> Self {
+        let indexes: Vec<_> = elements.iter().filter_map(index).collect();
+        Placement {
+            elements,
+            index,
+            in_place: indexes.windows(2).all(|pair| pair[0] < pair[1]),
+            placeholders: 0,
+            next: 0,
+        }
+    }
+
+    /// The elements to write at the next placeholder of the kind, none if
+    /// its element was moved or removed
+    fn next_placeholder(&mut self) -> &'a [T] {
+        let (elements, index) = (self.elements, self.index);
+        let added = |from: usize| {
+            elements[from..]
+                .iter()
+                .take_while(|element| index(element).is_none())
+                .count()
+        };
+
+        let start = self.next;
+        if !self.in_place {
+            self.next = elements.len();
+        } else {
+            // Elements added before the first parsed one go at the first placeholder
+            if self.placeholders == 0 {
+                self.next += added(self.next);
+            }
+            if elements.get(self.next).and_then(index) == Some(self.placeholders) {
+                self.next += 1;
+                self.next += added(self.next);
+            }
+        }
+        self.placeholders += 1;
+        &elements[start..self.next]
+    }
+
+    /// The elements no placeholder was left for
+    fn take_rest(&mut self) -> &'a [T] {
+        let start = self.next;
+        self.next = self.elements.len();
+        &self.elements[start..]
+    }
+}
+
+/// The blank lines waiting to be written before the next line. A run of
+/// blank lines is kept as it was, unless it surrounds placeholders left
+/// empty because their element moved or was removed, in which case it is
+/// written as a single blank line.
+#[derive(Default)]
+struct BlankLines {
+    count: usize,
+    emptied: bool,
+}
+
+impl BlankLines {
+    /// Writes the waiting blank lines followed by `line`, or notes an empty
+    /// placeholder if there is no line
+    fn write_before<T: Write>(&mut self, w: &mut T, line: &[u8]) -> IoResult<()> {
+        if line.is_empty() {
+            self.emptied = true;
+            return Ok(());
+        }
+        self.write_waiting(w)?;
+        w.write_all(line)
+    }
+
+    fn write_waiting<T: Write>(&mut self, w: &mut T) -> IoResult<()> {
+        let count = if self.emptied {
+            self.count.min(1)
+        } else {
+            self.count
+        };
+        for _ in 0..count {
+            writeln!(w)?;
+        }
+        *self = BlankLines::default();
+        Ok(())
+    }
+}
+
+/// Writes every element of `elements`, one per line
+fn write_all<T: fmt::Display, W: Write>(w: &mut W, elements: &[T]) -> IoResult<()> {
+    for element in elements {
+        writeln!(w, "{}", element)?;
+    }
+    Ok(())
+}
+
+impl MediaPlaylist {
+    /// Writes the MediaPlaylist to any `Write` type (e.g., file, buffer),
+    /// in the order recorded in `items`, with the original line endings
+    pub fn write_to<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        let mut lines = Vec::new();
+        writeln!(lines, "#EXTM3U")?;
+
+        let has = |kind: &MediaPlaylistItem| self.items.contains(kind);
+        let mut segments = Placement::new(&self.segments, |segment| segment.source.index);
+
+        // Playlist-wide tags that had no placeholder are written right after #EXTM3U
+        for kind in [
+            MediaPlaylistItem::Version,
+            MediaPlaylistItem::TargetDuration,
+            MediaPlaylistItem::MediaSequence,
+            MediaPlaylistItem::PlaylistType,
+            MediaPlaylistItem::IndependentSegments,
+        ] {
+            if !has(&kind) {
+                self.write_item(&mut lines, &kind, &mut segments)?;
+            }
+        }
+
+        let mut blank_lines = BlankLines::default();
+        let mut seen = Vec::new();
+        for item in &self.items {
+            match item {
+                MediaPlaylistItem::Blank => {
+                    blank_lines.count += 1;
+                    continue;
+                }
+                MediaPlaylistItem::Segment
+                | MediaPlaylistItem::Tag(_)
+                | MediaPlaylistItem::Comment(_)
+                | MediaPlaylistItem::Other(_) => {}
+                // A tag that may only appear once is written at its first placeholder
+                tag if seen.contains(&tag) => {
+                    blank_lines.emptied = true;
+                    continue;
+                }
+                tag => seen.push(tag),
+            }
+            let mut line = Vec::new();
+            self.write_item(&mut line, item, &mut segments)?;
+            blank_lines.write_before(&mut lines, &line)?;
+        }
+        blank_lines.write_waiting(&mut lines)?;
+
+        // Segments that had no placeholder go last, followed by EXT-X-ENDLIST
+        if !has(&MediaPlaylistItem::EndList) {
+            self.write_item(&mut lines, &MediaPlaylistItem::EndList, &mut segments)?;
+        }
+        self.line_endings.write(w, &lines)
+    }
+
+    /// Writes the line of an item. A segment placeholder writes its
+    /// segment, and EXT-X-ENDLIST writes any segments left before it.
+    fn write_item<T: Write>(
+        &self,
+        w: &mut T,
+        item: &MediaPlaylistItem,
+        segments: &mut Placement<'_, MediaSegment>,
+    ) -> IoResult<()> {
+        match item {
+            MediaPlaylistItem::Version => {
+                if let Some(version) = self.version {
+                    writeln!(w, "#EXT-X-VERSION:{}", version)?;
+                }
+            }
+            MediaPlaylistItem::TargetDuration => {
+                if let Some(target_duration) = self.target_duration {
+                    writeln!(w, "#EXT-X-TARGETDURATION:{}", target_duration)?;
+                }
+            }
+            MediaPlaylistItem::MediaSequence => {
+                if let Some(media_sequence) = self.media_sequence {
+                    writeln!(w, "#EXT-X-MEDIA-SEQUENCE:{}", media_sequence)?;
+                }
+            }
+            MediaPlaylistItem::PlaylistType => {
+                if let Some(playlist_type) = self.playlist_type {
+                    writeln!(w, "#EXT-X-PLAYLIST-TYPE:{}", playlist_type)?;
+                }
+            }
+            MediaPlaylistItem::IndependentSegments => {
+                if self.independent_segments {
+                    writeln!(w, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+                }
+            }
+            MediaPlaylistItem::Segment => write_all(w, segments.next_placeholder())?,
+            MediaPlaylistItem::EndList => {
+                write_all(w, segments.take_rest())?;
+                if self.end_list {
+                    writeln!(w, "#EXT-X-ENDLIST")?;
+                }
+            }
+            MediaPlaylistItem::Tag(line)
+            | MediaPlaylistItem::Comment(line)
+            | MediaPlaylistItem::Other(line) => writeln!(w, "{}", line)?,
+            MediaPlaylistItem::Blank => writeln!(w)?,
+        }
+        Ok(())
+    }
+}
+
+impl Playlist {
+    /// Writes the playlist to any `Write` type (e.g., file, buffer)
+    pub fn write_to<T: Write>(&self, w: &mut T) -> IoResult<()> {
+        match self {
+            Playlist::Master(playlist) => playlist.write_to(w),
+            Playlist::Media(playlist) => playlist.write_to(w),
+        }
+    }
+}
+
+/// Options controlling how a playlist is parsed. The default is lenient,
+/// as for the entry points that take no options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Fail on malformed values, missing required attributes and unknown
+    /// enumerated values, instead of recovering from them
+    pub strict: bool,
+}
+
+impl ParseOptions {
+    /// Options for strict parsing
+    pub fn strict() -> Self {
+        ParseOptions { strict: true }
+    }
+}
+
+/// Main function to parse the entire M3U8 playlist. Parsing is lenient and
+/// the recoveries made are discarded, see `parse_playlist_with_options` to
+/// get them or to parse strictly instead.
+pub fn parse_playlist(input: &str) -> Result<MasterPlaylist, PlaylistError> {
+    let (playlist, _) = parse_playlist_with_options(input, ParseOptions::default())?;
     Ok(playlist)
 }
 
-fn parse_master_playlist(input: &str) -> IResult<&str, MasterPlaylist> {
-    let (input, _) = parse_extm3u(input)?; // Parse the #EXTM3U tag
+/// Parses an M3U8 master playlist with the given options, returning the
+/// recoveries made in lenient mode along with the playlist
+pub fn parse_playlist_with_options(
+    input: &str,
+    options: ParseOptions,
+) -> Result<(MasterPlaylist, Vec<Diagnostic>), PlaylistError> {
+    let mut context = Context::new(options);
+    let (_, playlist) = parse_master_playlist(input, &mut context).map_err(map_nom_error(input))?;
+    Ok((playlist, context.into_diagnostics(input)))
+}
 
-    // Set independent_segments based on #EXT-X-INDEPENDENT-SEGMENTS presence
-    let (mut input, independent_segments) = match opt(parse_ext_x_independent_segments)(input)? {
-        (new_input, Some(_)) => (new_input, true),
-        (new_input, None) => (new_input, false),
-    };
+/// Parses an M3U8 media playlist. Parsing is lenient.
+pub fn parse_media_playlist(input: &str) -> Result<MediaPlaylist, PlaylistError> {
+    let (playlist, _) = parse_media_playlist_with_options(input, ParseOptions::default())?;
+    Ok(playlist)
+}
+
+/// Parses an M3U8 media playlist with the given options
+pub fn parse_media_playlist_with_options(
+    input: &str,
+    options: ParseOptions,
+) -> Result<(MediaPlaylist, Vec<Diagnostic>), PlaylistError> {
+    let mut context = Context::new(options);
+    let (_, playlist) =
+        parse_media_playlist_tags(input, &mut context).map_err(map_nom_error(input))?;
+    Ok((playlist, context.into_diagnostics(input)))
+}
+
+/// Parses an M3U8 playlist of either kind. A playlist containing any media
+/// segment tags is parsed as a media playlist, anything else as a master
+/// playlist. Parsing is lenient.
+pub fn parse_any_playlist(input: &str) -> Result<Playlist, PlaylistError> {
+    let (playlist, _) = parse_any_playlist_with_options(input, ParseOptions::default())?;
+    Ok(playlist)
+}
+
+/// Parses an M3U8 playlist of either kind with the given options
+pub fn parse_any_playlist_with_options(
+    input: &str,
+    options: ParseOptions,
+) -> Result<(Playlist, Vec<Diagnostic>), PlaylistError> {
+    if is_media_playlist(input) {
+        parse_media_playlist_with_options(input, options)
+            .map(|(playlist, diagnostics)| (Playlist::Media(playlist), diagnostics))
+    } else {
+        parse_playlist_with_options(input, options)
+            .map(|(playlist, diagnostics)| (Playlist::Master(Box::new(playlist)), diagnostics))
+    }
+}
+
+/// Checks for the tags that may only appear in a media playlist
+fn is_media_playlist(input: &str) -> bool {
+    input.lines().map(str::trim_start).any(|line| {
+        line.starts_with("#EXTINF:")
+            || line.starts_with("#EXT-X-TARGETDURATION:")
+            || line.starts_with("#EXT-X-MEDIA-SEQUENCE:")
+            || line.starts_with("#EXT-X-PLAYLIST-TYPE:")
+            || line.starts_with("#EXT-X-ENDLIST")
+    })
+}
+
+/// The error type of the nom parsers. `input` is the offending text, which
+/// is a slice of the document being parsed, so that it can be located in it.
+#[derive(Debug, PartialEq)]
+struct SyntaxError<'a> {
+    kind: SyntaxErrorKind,
+    input: &'a str,
+    length: usize,
+    tag: Option<&'static str>,
+    attribute: Option<&'a str>,
+    reason: String,
+}
+
+/// Which `PlaylistError` a `SyntaxError` is reported as
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SyntaxErrorKind {
+    Invalid,
+    MissingAttribute,
+    MissingUri,
+    MissingSegmentUri,
+    UndefinedVariable,
+    DuplicateVariable,
+}
+
+type ParseResult<'a, T> = IResult<&'a str, T, SyntaxError<'a>>;
+
+impl<'a> NomParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        let reason = match kind {
+            ErrorKind::Tag => "unexpected text",
+            ErrorKind::TakeWhile1 => "expected an attribute name",
+            ErrorKind::IsNot => "expected an attribute value",
+            ErrorKind::SeparatedList => "expected an attribute list",
+            ErrorKind::Digit => "expected a decimal integer",
+            _ => "invalid syntax",
+        };
+        SyntaxError {
+            length: 1,
+            ..SyntaxError::new(input, reason)
+        }
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> SyntaxError<'a> {
+    /// An error spanning all of `input`
+    fn new(input: &'a str, reason: &str) -> Self {
+        SyntaxError {
+            kind: SyntaxErrorKind::Invalid,
+            input,
+            length: input.len(),
+            tag: None,
+            attribute: None,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn in_tag(self, tag: &'static str) -> Self {
+        SyntaxError {
+            tag: Some(tag),
+            ..self
+        }
+    }
+
+    /// Locates the offending text in `document`, the whole input given to
+    /// the parser
+    fn locate(self, document: &str) -> ParseError {
+        let offset = document.offset(self.input);
+        let line_start = document[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = document[offset..]
+            .find(['\r', '\n'])
+            .map_or(document.len(), |i| offset + i);
+        let column = document[line_start..offset].chars().count() + 1;
+        let remaining = document[offset..line_end].chars().count();
+
+        ParseError {
+            line: document[..offset].matches('\n').count() + 1,
+            column,
+            length: self.length.min(remaining).max(1),
+            tag: self.tag.map(str::to_string),
+            attribute: self.attribute.map(str::to_string),
+            reason: self.reason,
+            source_line: document[line_start..line_end].to_string(),
+        }
+    }
+}
+
+/// Converts a nom error into a `PlaylistError`, locating it in `document`
+fn map_nom_error(document: &str) -> impl Fn(NomErr<SyntaxError>) -> PlaylistError + '_ {
+    move |e| match e {
+        NomErr::Incomplete(needed) => {
+            PlaylistError::Incomplete(format!("Incomplete input, needed: {:?}", needed))
+        }
+        NomErr::Error(error) | NomErr::Failure(error) => match error.kind {
+            SyntaxErrorKind::Invalid => PlaylistError::ParseError(error.locate(document)),
+            SyntaxErrorKind::MissingAttribute => {
+                PlaylistError::MissingAttribute(error.locate(document))
+            }
+            SyntaxErrorKind::MissingUri => PlaylistError::MissingUri(error.locate(document)),
+            SyntaxErrorKind::MissingSegmentUri => {
+                PlaylistError::MissingSegmentUri(error.locate(document))
+            }
+            SyntaxErrorKind::UndefinedVariable => PlaylistError::UndefinedVariable {
+                name: variable_name(error.input).to_string(),
+                location: Some(Box::new(error.locate(document))),
+            },
+            SyntaxErrorKind::DuplicateVariable => {
+                PlaylistError::DuplicateVariable(error.locate(document))
+            }
+        },
+    }
+}
 
+/// The parse options, the diagnostics collected in lenient mode and the
+/// names of the variables defined so far, threaded through the element
+/// parsers
+struct Context<'a> {
+    options: ParseOptions,
+    diagnostics: Vec<(SyntaxError<'a>, &'static str)>,
+    variables: HashSet<&'a str>,
+}
+
+impl<'a> Context<'a> {
+    fn new(options: ParseOptions) -> Self {
+        Context {
+            options,
+            diagnostics: Vec::new(),
+            variables: HashSet::new(),
+        }
+    }
+
+    /// Fails with `error` in strict mode. In lenient mode the error is
+    /// recorded along with the `recovery` made instead.
+    fn recover(
+        &mut self,
+        error: SyntaxError<'a>,
+        recovery: &'static str,
+    ) -> Result<(), NomErr<SyntaxError<'a>>> {
+        if self.options.strict {
+            return Err(NomErr::Failure(error));
+        }
+        self.diagnostics.push((error, recovery));
+        Ok(())
+    }
+
+    /// Fails in strict mode on an attribute value outside of its enumerated
+    /// set, which lenient mode keeps as it is
+    fn unknown_value(&mut self, attribute: &Attribute<'a>) -> Result<(), SyntaxError<'a>> {
+        let error = attribute.error("unknown enumerated value");
+        if self.options.strict {
+            return Err(error);
+        }
+        self.diagnostics.push((error, "kept the unknown value"));
+        Ok(())
+    }
+
+    /// Runs the parser of the element tag `tag_name`, naming the tag in its
+    /// errors and diagnostics
+    fn parse_tag<T>(
+        &mut self,
+        tag_name: &'static str,
+        input: &'a str,
+        parser: impl FnOnce(&'a str, &mut Self) -> ParseResult<'a, T>,
+    ) -> ParseResult<'a, T> {
+        let first = self.diagnostics.len();
+        let result = parser(input, self);
+        for (error, _) in &mut self.diagnostics[first..] {
+            error.tag.get_or_insert(tag_name);
+        }
+        result.map_err(|e| {
+            e.map(|mut error| {
+                error.tag.get_or_insert(tag_name);
+                error
+            })
+        })
+    }
+
+    /// Parses an attribute list that takes up the rest of its line. Text left
+    /// after the list is an error in strict mode, and ignored in lenient mode.
+    fn attribute_list(
+        &mut self,
+        input: &'a str,
+    ) -> Result<Vec<Attribute<'a>>, NomErr<SyntaxError<'a>>> {
+        let (rest, attributes) = parse_attribute_list(input, self)?;
+        let rest = rest.trim();
+        if !rest.is_empty() {
+            let error = SyntaxError::new(rest, "unexpected text after the attribute list");
+            self.recover(error, "ignored the rest of the line")?;
+        }
+        Ok(attributes)
+    }
+
+    /// Sets the fields of an element from its attributes using `set`, which
+    /// returns false for an attribute it does not know. Unknown attributes,
+    /// and in lenient mode attributes with an invalid value, are kept as extra
+    /// attributes. Returns false if one of the `required` attributes is
+    /// missing from the attribute list `section` or has an invalid value, in
+    /// which case lenient mode skips the element.
+    fn set_attributes<T>(
+        &mut self,
+        element: &mut T,
+        section: &'a str,
+        attributes: Vec<Attribute<'a>>,
+        required: &[&'static str],
+        set: fn(&mut T, &Attribute<'a>, &mut Self) -> Result<bool, SyntaxError<'a>>,
+        attribute_lists: fn(&mut T) -> (&mut Source, &mut Vec<ExtraAttribute>),
+    ) -> Result<bool, NomErr<SyntaxError<'a>>> {
+        let missing = required
+            .iter()
+            .find(|name| !attributes.iter().any(|attribute| attribute.name == **name));
+        if let Some(name) = missing {
+            self.recover(missing_attribute(section, name), "skipped the tag")?;
+            return Ok(false);
+        }
+
+        attribute_lists(element).0.attributes = written_attributes(&attributes);
+        for (position, attribute) in attributes.into_iter().enumerate() {
+            let known = match set(element, &attribute, self) {
+                Ok(known) => known,
+                Err(error) if required.contains(&attribute.name) => {
+                    self.recover(error, "skipped the tag")?;
+                    return Ok(false);
+                }
+                Err(error) => {
+                    self.recover(error, "kept the attribute as written")?;
+                    false
+                }
+            };
+            if !known {
+                attribute_lists(element)
+                    .1
+                    .push(attribute.into_extra(position));
+            }
+        }
+        Ok(true)
+    }
+
+    fn into_diagnostics(self, document: &str) -> Vec<Diagnostic> {
+        self.diagnostics
+            .into_iter()
+            .map(|(error, recovery)| Diagnostic {
+                error: error.locate(document),
+                recovery: recovery.to_string(),
+            })
+            .collect()
+    }
+}
+
+fn parse_master_playlist<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, MasterPlaylist> {
+    let line_endings = LineEndings::of(input);
+    let (mut input, _) = parse_extm3u(input)?; // Parse the #EXTM3U tag
+
+    let mut version = None;
+    let mut independent_segments = false;
+    let mut start = None;
+    let mut defines = Vec::new();
+    let mut content_steering = None;
+    let mut session_data = Vec::new();
+    let mut session_keys = Vec::new();
     let mut variants = Vec::new();
     let mut media = Vec::new();
     let mut frames = Vec::new();
+    let mut items = Vec::new();
+    let mut references = Vec::new();
 
-    // Loop through the input, parsing each tag dynamically
+    // Loop through the input, parsing each tag dynamically. Element parsers
+    // return None for an element skipped in lenient mode.
     while !input.is_empty() {
-        if input.starts_with("#EXT-X-I-FRAME-STREAM-INF") {
-            let (new_input, frame) = parse_iframe_stream(input)?;
-            frames.push(frame);
+        // Leading whitespace on a line is not significant
+        let (new_input, _) = space0(input)?;
+        input = new_input;
+        let (element, parsed) = (input, items.len());
+
+        if input.is_empty() {
+            break;
+        } else if let Ok((new_input, _)) = line_ending::<_, SyntaxError>(input) {
+            items.push(PlaylistItem::Blank);
+            input = new_input;
+        } else if input.starts_with("#EXT-X-I-FRAME-STREAM-INF") {
+            let (new_input, frame) =
+                context.parse_tag("#EXT-X-I-FRAME-STREAM-INF", input, parse_iframe_stream)?;
+            if let Some(mut frame) = frame {
+                frame.source.index = Some(frames.len());
+                frames.push(frame);
+                items.push(PlaylistItem::IFrame);
+            }
             input = new_input;
         } else if input.starts_with("#EXT-X-STREAM-INF") {
-            let (new_input, variant) = parse_stream_variant(input)?;
-            variants.push(variant);
+            let (new_input, variant) =
+                context.parse_tag("#EXT-X-STREAM-INF", input, parse_stream_variant)?;
+            if let Some(mut variant) = variant {
+                variant.source.index = Some(variants.len());
+                variants.push(variant);
+                items.push(PlaylistItem::Variant);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-MEDIA:") {
+            let (new_input, track) = context.parse_tag("#EXT-X-MEDIA", input, parse_media_track)?;
+            if let Some(mut track) = track {
+                track.source.index = Some(media.len());
+                media.push(track);
+                items.push(PlaylistItem::Media);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-INDEPENDENT-SEGMENTS") {
+            let (new_input, _) =
+                context.parse_tag("#EXT-X-INDEPENDENT-SEGMENTS", input, |input, _| {
+                    parse_ext_x_independent_segments(input)
+                })?;
+            independent_segments = true;
+            items.push(PlaylistItem::IndependentSegments);
+            input = new_input;
+        } else if input.starts_with("#EXT-X-VERSION:") {
+            let (new_input, number) =
+                context.parse_tag("#EXT-X-VERSION", input, parse_ext_x_version)?;
+            if number.is_some() {
+                version = number;
+                items.push(PlaylistItem::Version);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-START:") {
+            let (new_input, start_tag) = context.parse_tag("#EXT-X-START", input, parse_start)?;
+            if start_tag.is_some() {
+                start = start_tag;
+                items.push(PlaylistItem::Start);
+            }
             input = new_input;
-        } else if input.starts_with("#EXT-X-MEDIA") {
-            let (new_input, track) = parse_media_track(input)?;
-            media.push(track);
+        } else if input.starts_with("#EXT-X-DEFINE:") {
+            let (new_input, define) = context.parse_tag("#EXT-X-DEFINE", input, parse_define)?;
+            if let Some(mut define) = define {
+                define.source.index = Some(defines.len());
+                defines.push(define);
+                items.push(PlaylistItem::Define);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-CONTENT-STEERING:") {
+            let (new_input, steering) =
+                context.parse_tag("#EXT-X-CONTENT-STEERING", input, parse_content_steering)?;
+            if steering.is_some() {
+                content_steering = steering;
+                items.push(PlaylistItem::ContentSteering);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-SESSION-DATA:") {
+            let (new_input, data) =
+                context.parse_tag("#EXT-X-SESSION-DATA", input, parse_session_data)?;
+            if let Some(mut data) = data {
+                data.source.index = Some(session_data.len());
+                session_data.push(data);
+                items.push(PlaylistItem::SessionData);
+            }
+            input = new_input;
+        } else if input.starts_with("#EXT-X-SESSION-KEY:") {
+            let (new_input, key) =
+                context.parse_tag("#EXT-X-SESSION-KEY", input, parse_session_key)?;
+            if let Some(mut key) = key {
+                key.source.index = Some(session_keys.len());
+                session_keys.push(key);
+                items.push(PlaylistItem::SessionKey);
+            }
             input = new_input;
         } else {
-            // Skip over any unrecognized or non-relevant tags or lines
-            let (new_input, _) = not_line_ending(input)?;
-            let (new_input, _) = line_ending(new_input)?;
-            let (new_input, _) = multispace0(new_input)?;
+            // Keep any unrecognized tags, comments or lines as they are
+            let (new_input, line) = not_line_ending(input)?;
+            let (new_input, _) = opt(line_ending)(new_input)?;
+            let line = line.trim_end().to_string();
+            items.push(if line.starts_with("#EXT") {
+                PlaylistItem::Tag(line)
+            } else if line.starts_with('#') {
+                PlaylistItem::Comment(line)
+            } else {
+                PlaylistItem::Other(line)
+            });
             input = new_input;
         }
+
+        // Variable references are substituted in the elements, but not in
+        // the EXT-X-DEFINE tags or in the lines kept as they are
+        if matches!(
+            items.get(parsed),
+            Some(
+                PlaylistItem::ContentSteering
+                    | PlaylistItem::SessionData
+                    | PlaylistItem::SessionKey
+                    | PlaylistItem::Media
+                    | PlaylistItem::Variant
+                    | PlaylistItem::IFrame
+            )
+        ) {
+            references.extend(variable_references(&element[..element.len() - input.len()]));
+        }
+    }
+
+    for reference in references {
+        if !context.variables.contains(variable_name(reference)) {
+            let error = SyntaxError {
+                kind: SyntaxErrorKind::UndefinedVariable,
+                ..SyntaxError::new(reference, "reference to an undefined variable")
+            };
+            context.recover(error, "kept the reference as written")?;
+        }
     }
 
     Ok((
         input,
         MasterPlaylist {
+            version,
             independent_segments,
+            start,
+            defines,
+            content_steering,
+            session_data,
+            session_keys,
             variants,
             media,
             frames,
+            items,
+            line_endings,
         },
     ))
 }
 
-fn parse_stream_variant(input: &str) -> IResult<&str, StreamVariant> {
+fn parse_media_playlist_tags<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, MediaPlaylist> {
+    let line_endings = LineEndings::of(input);
+    let (mut input, _) = parse_extm3u(input)?; // Parse the #EXTM3U tag
+
+    let mut playlist = MediaPlaylist {
+        version: None,
+        target_duration: None,
+        media_sequence: None,
+        playlist_type: None,
+        independent_segments: false,
+        end_list: false,
+        segments: Vec::new(),
+        items: Vec::new(),
+        line_endings,
+    };
+
+    // The EXTINF tag applies to the next URI line in the playlist. Any tags
+    // in between are kept before the segment.
+    let mut pending_segment: Option<(&'a str, f64, &'a str, Option<String>)> = None;
+
+    // Loop through the input, one line at a time
+    while !input.is_empty() {
+        let (new_input, line) = not_line_ending(input)?;
+        let (new_input, _) = opt(line_ending)(new_input)?;
+        input = new_input;
+
+        let line = line.trim();
+        let item = if line.is_empty() {
+            MediaPlaylistItem::Blank
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            if let Some((extinf, ..)) = pending_segment.take() {
+                context.recover(missing_segment_uri(extinf), "skipped the tag")?;
+            }
+            let (duration, title) = match value.split_once(',') {
+                Some((duration, title)) => (duration.trim(), Some(title.trim())),
+                None => (value.trim(), None),
+            };
+            let title = title.filter(|t| !t.is_empty()).map(str::to_string);
+            let duration_text = duration;
+            let duration = match duration.parse() {
+                Ok(duration) => duration,
+                Err(_) => {
+                    let error = SyntaxError::new(duration, "expected a decimal duration");
+                    context.recover(error.in_tag("#EXTINF"), "used a duration of 0")?;
+                    0.0
+                }
+            };
+            pending_segment = Some((line, duration, duration_text, title));
+            continue;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-VERSION:") {
+            playlist.version =
+                parse_tag_value(value, "#EXT-X-VERSION", context, |value| value.parse().ok())?;
+            match playlist.version {
+                Some(_) => MediaPlaylistItem::Version,
+                None => continue,
+            }
+        } else if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            playlist.target_duration =
+                parse_tag_value(value, "#EXT-X-TARGETDURATION", context, |value| {
+                    value.parse().ok()
+                })?;
+            match playlist.target_duration {
+                Some(_) => MediaPlaylistItem::TargetDuration,
+                None => continue,
+            }
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            playlist.media_sequence =
+                parse_tag_value(value, "#EXT-X-MEDIA-SEQUENCE", context, |value| {
+                    value.parse().ok()
+                })?;
+            match playlist.media_sequence {
+                Some(_) => MediaPlaylistItem::MediaSequence,
+                None => continue,
+            }
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PLAYLIST-TYPE:") {
+            playlist.playlist_type = parse_tag_value(
+                value,
+                "#EXT-X-PLAYLIST-TYPE",
+                context,
+                |value| match value {
+                    "EVENT" => Some(PlaylistType::Event),
+                    "VOD" => Some(PlaylistType::Vod),
+                    _ => None,
+                },
+            )?;
+            match playlist.playlist_type {
+                Some(_) => MediaPlaylistItem::PlaylistType,
+                None => continue,
+            }
+        } else if line == "#EXT-X-INDEPENDENT-SEGMENTS" {
+            playlist.independent_segments = true;
+            MediaPlaylistItem::IndependentSegments
+        } else if line == "#EXT-X-ENDLIST" {
+            playlist.end_list = true;
+            MediaPlaylistItem::EndList
+        } else if line.starts_with("#EXT") {
+            MediaPlaylistItem::Tag(line.to_string())
+        } else if line.starts_with('#') {
+            MediaPlaylistItem::Comment(line.to_string())
+        } else if let Some((_, duration, duration_text, title)) = pending_segment.take() {
+            // A URI line completes the segment started by the preceding EXTINF tag
+            playlist.segments.push(MediaSegment {
+                duration,
+                title,
+                uri: line.to_string(),
+                source: Source {
+                    index: Some(playlist.segments.len()),
+                    attributes: vec![("DURATION".to_string(), duration_text.to_string())],
+                },
+            });
+            MediaPlaylistItem::Segment
+        } else {
+            let error = SyntaxError::new(line, "expected an #EXTINF tag before the URI");
+            context.recover(error, "kept the line as written")?;
+            MediaPlaylistItem::Other(line.to_string())
+        };
+        playlist.items.push(item);
+    }
+
+    if let Some((extinf, ..)) = pending_segment {
+        context.recover(missing_segment_uri(extinf), "skipped the tag")?;
+    }
+
+    Ok((input, playlist))
+}
+
+/// The error for an EXTINF tag that no URI line follows, reported at the
+/// end of the tag
+fn missing_segment_uri(extinf: &str) -> SyntaxError<'_> {
+    SyntaxError {
+        kind: SyntaxErrorKind::MissingSegmentUri,
+        ..SyntaxError::new(&extinf[extinf.len()..], "expected a URI line after the tag")
+            .in_tag("#EXTINF")
+    }
+}
+
+/// Parses the value of a tag, which lenient mode ignores when it is
+/// invalid
+fn parse_tag_value<'a, T>(
+    value: &'a str,
+    tag_name: &'static str,
+    context: &mut Context<'a>,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>, NomErr<SyntaxError<'a>>> {
+    let value = value.trim();
+    let parsed = parse(value);
+    if parsed.is_none() {
+        let error = SyntaxError::new(value, "invalid value").in_tag(tag_name);
+        context.recover(error, "ignored the tag")?;
+    }
+    Ok(parsed)
+}
+
+fn parse_stream_variant<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<StreamVariant>> {
     let (input, _) = multispace0(input)?;
     let (input, _) = tag("#EXT-X-STREAM-INF:")(input)?;
     let (input, key_value_section) = not_line_ending(input)?; // Parse until the end of the line, then handle key-value pairs
 
     // Parse the key-value pairs from the line
-    let (_, key_value_pairs) = separated_list1(
-        tag(","),
-        separated_pair(parse_key, tag("="), parse_quoted_or_unquoted_string),
-    )(key_value_section)?;
+    let attributes = context.attribute_list(key_value_section)?;
+
+    // Now parse the URI, which comes after the key-value section and a newline
+    let (uri_input, _) = multispace0(input)?;
+    if uri_input.is_empty() || uri_input.starts_with('#') {
+        let end_of_line = &key_value_section[key_value_section.len()..];
+        let error = SyntaxError {
+            kind: SyntaxErrorKind::MissingUri,
+            ..SyntaxError::new(end_of_line, "expected a URI line after the tag")
+        };
+        context.recover(error, "skipped the tag")?;
+        let (input, _) = opt(line_ending)(input)?;
+        return Ok((input, None));
+    }
+    let (input, uri) = parse_uri(uri_input)?;
+    let (input, _) = opt(line_ending)(input)?;
 
     // Initialize the StreamVariant struct with default values
     let mut stream_variant = StreamVariant {
+        program_id: None,
         bandwidth: 0,
         average_bandwidth: None,
+        score: None,
         codecs: None,
+        supplemental_codecs: None,
         resolution: None,
         frame_rate: None,
+        hdcp_level: None,
+        allowed_cpc: None,
         video_range: None,
+        req_video_layout: None,
+        stable_variant_id: None,
         audio: None,
+        video: None,
+        subtitles: None,
         closed_captions: None,
-        uri: String::new(),
+        pathway_id: None,
+        uri,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
     };
 
-    // Iterate over the key-value pairs and populate the struct
-    for (key, value) in key_value_pairs {
-        match key.as_str() {
-            "BANDWIDTH" => stream_variant.bandwidth = value.parse().unwrap_or(0),
-            "AVERAGE-BANDWIDTH" => {
-                stream_variant.average_bandwidth = Some(value.parse().unwrap_or(0))
-            }
-            "CODECS" => stream_variant.codecs = Some(value),
-            "RESOLUTION" => {
-                let res_parts: Vec<&str> = value.split('x').collect();
-                if res_parts.len() == 2 {
-                    if let (Ok(width), Ok(height)) = (res_parts[0].parse(), res_parts[1].parse()) {
-                        stream_variant.resolution = Some((width, height));
-                    }
-                }
+    let complete = context.set_attributes(
+        &mut stream_variant,
+        key_value_section,
+        attributes,
+        &["BANDWIDTH"],
+        set_stream_variant_attribute,
+        |variant| (&mut variant.source, &mut variant.extra_attributes),
+    )?;
+
+    Ok((input, complete.then_some(stream_variant)))
+}
+
+fn set_stream_variant_attribute<'a>(
+    stream_variant: &mut StreamVariant,
+    attribute: &Attribute<'a>,
+    context: &mut Context<'a>,
+) -> Result<bool, SyntaxError<'a>> {
+    let value = attribute.value.to_string();
+    match attribute.name {
+        "PROGRAM-ID" => stream_variant.program_id = Some(attribute.parse()?),
+        "BANDWIDTH" => stream_variant.bandwidth = attribute.parse()?,
+        "AVERAGE-BANDWIDTH" => stream_variant.average_bandwidth = Some(attribute.parse()?),
+        "SCORE" => stream_variant.score = Some(attribute.parse()?),
+        "CODECS" => stream_variant.codecs = Some(attribute.non_empty()?),
+        "SUPPLEMENTAL-CODECS" => stream_variant.supplemental_codecs = Some(value),
+        "RESOLUTION" => stream_variant.resolution = Some(attribute.resolution()?),
+        "FRAME-RATE" => stream_variant.frame_rate = Some(attribute.parse()?),
+        "HDCP-LEVEL" => {
+            let hdcp_level = HdcpLevel::from(value);
+            if let HdcpLevel::Other(_) = hdcp_level {
+                context.unknown_value(attribute)?;
             }
-            "FRAME-RATE" => stream_variant.frame_rate = Some(value.parse().unwrap_or(0.0)),
-            "VIDEO-RANGE" => stream_variant.video_range = Some(value),
-            "AUDIO" => stream_variant.audio = Some(value),
-            "CLOSED-CAPTIONS" => stream_variant.closed_captions = Some(value),
-            _ => {}
+            stream_variant.hdcp_level = Some(hdcp_level);
+        }
+        "ALLOWED-CPC" => stream_variant.allowed_cpc = Some(value),
+        "VIDEO-RANGE" => stream_variant.video_range = Some(attribute.video_range(context)?),
+        "REQ-VIDEO-LAYOUT" => stream_variant.req_video_layout = Some(value),
+        "STABLE-VARIANT-ID" => stream_variant.stable_variant_id = Some(value),
+        "AUDIO" => stream_variant.audio = Some(attribute.non_empty()?),
+        "VIDEO" => stream_variant.video = Some(attribute.non_empty()?),
+        "SUBTITLES" => stream_variant.subtitles = Some(attribute.non_empty()?),
+        "CLOSED-CAPTIONS" => {
+            stream_variant.closed_captions = Some(match (attribute.value, attribute.quoted) {
+                (_, true) => ClosedCaptions::GroupId(attribute.non_empty()?),
+                ("NONE", false) => ClosedCaptions::None,
+                _ => return Err(attribute.error("expected a quoted GROUP-ID or NONE")),
+            })
         }
+        "PATHWAY-ID" => stream_variant.pathway_id = Some(value),
+        _ => return Ok(false),
     }
-
-    // Now parse the URI, which comes after the key-value section and a newline
-    let (input, _) = multispace0(input)?;
-    let (input, uri) = parse_uri(input)?;
-    let (input, _) = multispace0(input)?;
-
-    stream_variant.uri = uri;
-
-    Ok((input, stream_variant))
+    Ok(true)
 }
 
-fn parse_media_track(input: &str) -> IResult<&str, MediaTrack> {
+fn parse_media_track<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<MediaTrack>> {
     let (input, _) = multispace0(input)?;
     let (input, _) = tag("#EXT-X-MEDIA:")(input)?;
 
-    // Split the input into key-value pairs by commas
-    let (input, key_value_pairs) = separated_list1(
-        tag(","),
-        separated_pair(parse_key, tag("="), parse_quoted_or_unquoted_string),
-    )(input)?;
-
-    let (input, _) = multispace0(input)?;
+    // Split the input into key-value pairs by commas. The attributes may
+    // continue over several lines.
+    let (rest, attributes) = parse_attribute_list(input, context)?;
+    let key_value_section = &input[..input.len() - rest.len()];
+
+    // Anything after the attribute list is read as a line of its own
+    let (junk, _) = space0(rest)?;
+    let input = match junk.chars().next() {
+        None | Some('\r') | Some('\n') => opt(line_ending)(junk)?.0,
+        Some(_) => {
+            let error = SyntaxError::new(junk, "unexpected text after the attribute list");
+            context.recover(error, "kept the text as its own line")?;
+            junk
+        }
+    };
 
     // Accumulate the key-value pairs into a MediaTrack struct
     let mut track = MediaTrack {
-        track_type: None,
-        group_id: None,
-        name: None,
+        track_type: MediaType::Audio,
+        group_id: String::new(),
+        name: String::new(),
         language: None,
+        assoc_language: None,
+        stable_rendition_id: None,
         default: None,
         autoselect: None,
+        forced: None,
+        instream_id: None,
+        bit_depth: None,
+        sample_rate: None,
+        characteristics: None,
         channels: None,
         uri: None,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
     };
 
-    for (key, value) in key_value_pairs {
-        match key.as_str() {
-            "TYPE" => track.track_type = Some(value),
-            "GROUP-ID" => track.group_id = Some(value),
-            "NAME" => track.name = Some(value),
-            "LANGUAGE" => track.language = Some(value),
-            "DEFAULT" => track.default = Some(value),
-            "AUTOSELECT" => track.autoselect = Some(value),
-            "CHANNELS" => track.channels = Some(value),
-            "URI" => track.uri = Some(value),
-            _ => {} // Ignore unknown fields
+    let complete = context.set_attributes(
+        &mut track,
+        key_value_section,
+        attributes,
+        &["TYPE", "GROUP-ID", "NAME"],
+        set_media_track_attribute,
+        |track| (&mut track.source, &mut track.extra_attributes),
+    )?;
+
+    Ok((input, complete.then_some(track)))
+}
+
+fn set_media_track_attribute<'a>(
+    track: &mut MediaTrack,
+    attribute: &Attribute<'a>,
+    _: &mut Context<'a>,
+) -> Result<bool, SyntaxError<'a>> {
+    let value = attribute.value.to_string();
+    match attribute.name {
+        "TYPE" => {
+            track.track_type = parse_media_type(&value).ok_or_else(|| {
+                attribute.error("expected AUDIO, VIDEO, SUBTITLES or CLOSED-CAPTIONS")
+            })?
         }
+        "GROUP-ID" => track.group_id = attribute.non_empty()?,
+        "NAME" => track.name = attribute.non_empty()?,
+        "LANGUAGE" => track.language = Some(value),
+        "ASSOC-LANGUAGE" => track.assoc_language = Some(value),
+        "STABLE-RENDITION-ID" => track.stable_rendition_id = Some(value),
+        "DEFAULT" => track.default = Some(attribute.yes_no()?),
+        "AUTOSELECT" => track.autoselect = Some(attribute.yes_no()?),
+        "FORCED" => track.forced = Some(attribute.yes_no()?),
+        "INSTREAM-ID" => track.instream_id = Some(value),
+        "BIT-DEPTH" => track.bit_depth = Some(attribute.parse()?),
+        "SAMPLE-RATE" => track.sample_rate = Some(attribute.parse()?),
+        "CHARACTERISTICS" => track.characteristics = Some(value),
+        "CHANNELS" => track.channels = Some(value),
+        "URI" => track.uri = Some(attribute.non_empty()?),
+        _ => return Ok(false),
     }
-
-    Ok((input, track))
+    Ok(true)
 }
 
-fn parse_iframe_stream(input: &str) -> IResult<&str, IFrameStream> {
+fn parse_iframe_stream<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<IFrameStream>> {
     let (input, _) = multispace0(input)?;
     let (input, _) = tag("#EXT-X-I-FRAME-STREAM-INF:")(input)?;
     let (input, key_value_section) = not_line_ending(input)?; // Parse until the end of the line, then handle key-value pairs
-    let (input, _) = multispace0(input)?;
+    let (input, _) = opt(line_ending)(input)?;
 
     // Parse the key-value pairs from the line
-    let (_, key_value_pairs) = separated_list1(
-        tag(","),
-        separated_pair(parse_key, tag("="), parse_quoted_or_unquoted_string),
-    )(key_value_section)?;
-
-    let (input, _) = multispace0(input)?;
+    let attributes = context.attribute_list(key_value_section)?;
 
     // Initialize the IFrameStream struct with default values
     let mut iframe_stream = IFrameStream {
@@ -383,83 +2131,557 @@ fn parse_iframe_stream(input: &str) -> IResult<&str, IFrameStream> {
         codecs: None,
         resolution: None,
         video_range: None,
+        pathway_id: None,
         uri: String::new(),
+        source: Source::default(),
+        extra_attributes: Vec::new(),
     };
 
-    // Iterate over the key-value pairs and populate the struct
-    for (key, value) in key_value_pairs {
-        match key.as_str() {
-            "BANDWIDTH" => iframe_stream.bandwidth = value.parse().unwrap_or(0),
-            "CODECS" => iframe_stream.codecs = Some(value),
-            "RESOLUTION" => {
-                let res_parts: Vec<&str> = value.split('x').collect();
-                if res_parts.len() == 2 {
-                    if let (Ok(width), Ok(height)) = (res_parts[0].parse(), res_parts[1].parse()) {
-                        iframe_stream.resolution = Some((width, height));
+    let complete = context.set_attributes(
+        &mut iframe_stream,
+        key_value_section,
+        attributes,
+        &["BANDWIDTH", "URI"],
+        set_iframe_stream_attribute,
+        |frame| (&mut frame.source, &mut frame.extra_attributes),
+    )?;
+
+    Ok((input, complete.then_some(iframe_stream)))
+}
+
+fn set_iframe_stream_attribute<'a>(
+    iframe_stream: &mut IFrameStream,
+    attribute: &Attribute<'a>,
+    context: &mut Context<'a>,
+) -> Result<bool, SyntaxError<'a>> {
+    let value = attribute.value.to_string();
+    match attribute.name {
+        "BANDWIDTH" => iframe_stream.bandwidth = attribute.parse()?,
+        "CODECS" => iframe_stream.codecs = Some(attribute.non_empty()?),
+        "RESOLUTION" => iframe_stream.resolution = Some(attribute.resolution()?),
+        "VIDEO-RANGE" => iframe_stream.video_range = Some(attribute.video_range(context)?),
+        "PATHWAY-ID" => iframe_stream.pathway_id = Some(value),
+        "URI" => iframe_stream.uri = attribute.non_empty()?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn parse_define<'a>(input: &'a str, context: &mut Context<'a>) -> ParseResult<'a, Option<Define>> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXT-X-DEFINE:")(input)?;
+    let (input, key_value_section) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let attributes = context.attribute_list(key_value_section)?;
+
+    let mut name = None;
+    let mut value = None;
+    let mut import = None;
+    let mut query_param = None;
+    let written_attributes = written_attributes(&attributes);
+    let mut extra_attributes = Vec::new();
+
+    for (position, attribute) in attributes.into_iter().enumerate() {
+        match attribute.name {
+            "NAME" => name = Some(attribute),
+            "VALUE" => value = Some(attribute),
+            "IMPORT" => import = Some(attribute),
+            "QUERYPARAM" => query_param = Some(attribute),
+            _ => extra_attributes.push(attribute.into_extra(position)),
+        }
+    }
+
+    // Exactly one of NAME (with VALUE), IMPORT or QUERYPARAM must be present
+    let (variable, definition) = match (name, value, import, query_param) {
+        (Some(name), Some(value), None, None) => {
+            let definition = Definition::Value {
+                name: name.value.to_string(),
+                value: value.value.to_string(),
+            };
+            (name, definition)
+        }
+        (None, None, Some(name), None) => {
+            let definition = Definition::Import(name.value.to_string());
+            (name, definition)
+        }
+        (None, None, None, Some(name)) => {
+            let definition = Definition::QueryParam(name.value.to_string());
+            (name, definition)
+        }
+        _ => {
+            let error = SyntaxError::new(
+                key_value_section,
+                "expected either NAME and VALUE, IMPORT or QUERYPARAM",
+            );
+            context.recover(error, "skipped the tag")?;
+            return Ok((input, None));
+        }
+    };
+
+    if variable.value.is_empty() {
+        context.recover(
+            variable.error("expected a non-empty value"),
+            "skipped the tag",
+        )?;
+        return Ok((input, None));
+    }
+
+    // A variable may only be defined once, the first definition is kept
+    if !context.variables.insert(variable.value) {
+        let error = SyntaxError {
+            kind: SyntaxErrorKind::DuplicateVariable,
+            ..variable.error("variable is already defined")
+        };
+        context.recover(error, "skipped the tag")?;
+        return Ok((input, None));
+    }
+
+    let define = Define {
+        definition,
+        source: Source {
+            index: None,
+            attributes: written_attributes,
+        },
+        extra_attributes,
+    };
+    Ok((input, Some(define)))
+}
+
+fn parse_content_steering<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<ContentSteering>> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXT-X-CONTENT-STEERING:")(input)?;
+    let (input, key_value_section) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let attributes = context.attribute_list(key_value_section)?;
+
+    let mut content_steering = ContentSteering {
+        server_uri: String::new(),
+        pathway_id: None,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
+    };
+
+    let complete = context.set_attributes(
+        &mut content_steering,
+        key_value_section,
+        attributes,
+        &["SERVER-URI"],
+        |content_steering, attribute, _| {
+            let value = attribute.value.to_string();
+            match attribute.name {
+                "SERVER-URI" => content_steering.server_uri = attribute.non_empty()?,
+                "PATHWAY-ID" => content_steering.pathway_id = Some(value),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        },
+        |content_steering| {
+            (
+                &mut content_steering.source,
+                &mut content_steering.extra_attributes,
+            )
+        },
+    )?;
+
+    Ok((input, complete.then_some(content_steering)))
+}
+
+fn parse_session_data<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<SessionData>> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXT-X-SESSION-DATA:")(input)?;
+    let (input, key_value_section) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let attributes = context.attribute_list(key_value_section)?;
+
+    let mut session_data = SessionData {
+        data_id: String::new(),
+        value: None,
+        uri: None,
+        format: None,
+        language: None,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
+    };
+
+    let complete = context.set_attributes(
+        &mut session_data,
+        key_value_section,
+        attributes,
+        &["DATA-ID"],
+        |session_data, attribute, _| {
+            let value = attribute.value.to_string();
+            match attribute.name {
+                "DATA-ID" => session_data.data_id = attribute.non_empty()?,
+                "VALUE" => session_data.value = Some(value),
+                "URI" => session_data.uri = Some(value),
+                "FORMAT" => {
+                    session_data.format = Some(match attribute.value {
+                        "JSON" => SessionDataFormat::Json,
+                        "RAW" => SessionDataFormat::Raw,
+                        _ => return Err(attribute.error("expected JSON or RAW")),
+                    })
+                }
+                "LANGUAGE" => session_data.language = Some(value),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        },
+        |session_data| (&mut session_data.source, &mut session_data.extra_attributes),
+    )?;
+
+    Ok((input, complete.then_some(session_data)))
+}
+
+fn parse_session_key<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<SessionKey>> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXT-X-SESSION-KEY:")(input)?;
+    let (input, key_value_section) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let attributes = context.attribute_list(key_value_section)?;
+
+    let mut session_key = SessionKey {
+        method: KeyMethod::None,
+        uri: None,
+        iv: None,
+        keyformat: None,
+        keyformatversions: None,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
+    };
+
+    let complete = context.set_attributes(
+        &mut session_key,
+        key_value_section,
+        attributes,
+        &["METHOD"],
+        |session_key, attribute, context| {
+            let value = attribute.value.to_string();
+            match attribute.name {
+                "METHOD" => {
+                    session_key.method = KeyMethod::from(value);
+                    if let KeyMethod::Other(_) = session_key.method {
+                        context.unknown_value(attribute)?;
                     }
                 }
+                "URI" => session_key.uri = Some(value),
+                "IV" => session_key.iv = Some(value),
+                "KEYFORMAT" => session_key.keyformat = Some(value),
+                "KEYFORMATVERSIONS" => session_key.keyformatversions = Some(value),
+                _ => return Ok(false),
             }
-            "VIDEO-RANGE" => iframe_stream.video_range = Some(value),
-            "URI" => iframe_stream.uri = value,
-            _ => {}
-        }
-    }
+            Ok(true)
+        },
+        |session_key| (&mut session_key.source, &mut session_key.extra_attributes),
+    )?;
 
-    Ok((input, iframe_stream))
+    Ok((input, complete.then_some(session_key)))
 }
 
-fn parse_extm3u(input: &str) -> IResult<&str, ()> {
+fn parse_start<'a>(input: &'a str, context: &mut Context<'a>) -> ParseResult<'a, Option<Start>> {
     let (input, _) = multispace0(input)?;
-    let (input, _) = tag("#EXTM3U")(input)?;
+    let (input, _) = tag("#EXT-X-START:")(input)?;
+    let (input, key_value_section) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let attributes = context.attribute_list(key_value_section)?;
+
+    let mut start = Start {
+        time_offset: 0.0,
+        precise: None,
+        source: Source::default(),
+        extra_attributes: Vec::new(),
+    };
+
+    let complete = context.set_attributes(
+        &mut start,
+        key_value_section,
+        attributes,
+        &["TIME-OFFSET"],
+        |start, attribute, _| {
+            match attribute.name {
+                "TIME-OFFSET" => start.time_offset = attribute.parse()?,
+                "PRECISE" => start.precise = Some(attribute.yes_no()?),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        },
+        |start| (&mut start.source, &mut start.extra_attributes),
+    )?;
+
+    Ok((input, complete.then_some(start)))
+}
+
+fn parse_ext_x_version<'a>(
+    input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Option<u32>> {
     let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXT-X-VERSION:")(input)?;
+    let (input, value) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+    let number = parse_tag_value(value, "#EXT-X-VERSION", context, |value| value.parse().ok())?;
+    Ok((input, number))
+}
+
+fn parse_extm3u(input: &str) -> ParseResult<'_, ()> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("#EXTM3U")(input).map_err(|e: NomErr<SyntaxError>| {
+        e.map(|error| SyntaxError {
+            reason: "expected the playlist to start with #EXTM3U".to_string(),
+            ..error
+        })
+    })?;
+    let (input, _) = space0(input)?;
+    let (input, _) = opt(line_ending)(input)?;
     Ok((input, ()))
 }
 
-fn parse_ext_x_independent_segments(input: &str) -> IResult<&str, ()> {
+fn parse_ext_x_independent_segments(input: &str) -> ParseResult<'_, ()> {
     let (input, _) = multispace0(input)?;
     let (input, _) = tag("#EXT-X-INDEPENDENT-SEGMENTS")(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = opt(line_ending)(input)?;
     Ok((input, ()))
 }
 
-fn parse_uri(input: &str) -> IResult<&str, String> {
+/// Parses the TYPE attribute of an EXT-X-MEDIA tag
+fn parse_media_type(value: &str) -> Option<MediaType> {
+    match value {
+        "AUDIO" => Some(MediaType::Audio),
+        "VIDEO" => Some(MediaType::Video),
+        "SUBTITLES" => Some(MediaType::Subtitles),
+        "CLOSED-CAPTIONS" => Some(MediaType::ClosedCaptions),
+        _ => None,
+    }
+}
+
+/// Parses a YES/NO enumerated-string
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value {
+        "YES" => Some(true),
+        "NO" => Some(false),
+        _ => None,
+    }
+}
+
+/// The error for a tag that lacks a required attribute, reported at its attribute list
+fn missing_attribute<'a>(input: &'a str, name: &'static str) -> SyntaxError<'a> {
+    SyntaxError {
+        kind: SyntaxErrorKind::MissingAttribute,
+        attribute: Some(name),
+        ..SyntaxError::new(input, "missing required attribute")
+    }
+}
+
+fn parse_uri(input: &str) -> ParseResult<'_, String> {
     let (input, uri) = not_line_ending(input)?;
     Ok((input, uri.to_string()))
 }
 
+/// The names and values of the attributes of a tag, in the order they were
+/// written
+fn written_attributes(attributes: &[Attribute]) -> Vec<(String, String)> {
+    attributes
+        .iter()
+        .map(|attribute| (attribute.name.to_string(), attribute.value.to_string()))
+        .collect()
+}
+
+/// An attribute as written in the tag, with the quotes removed from its value
+#[derive(Debug)]
+struct Attribute<'a> {
+    name: &'a str,
+    value: &'a str,
+    quoted: bool,
+}
+
+impl<'a> Attribute<'a> {
+    /// The error for an invalid value of this attribute, reported at the value
+    fn error(&self, reason: &str) -> SyntaxError<'a> {
+        SyntaxError {
+            attribute: Some(self.name),
+            ..SyntaxError::new(self.value, reason)
+        }
+    }
+
+    /// Parses a decimal-integer or decimal-floating-point value
+    fn parse<T: FromStr>(&self) -> Result<T, SyntaxError<'a>> {
+        self.value
+            .parse()
+            .map_err(|_| self.error("expected a decimal number"))
+    }
+
+    /// Parses a decimal-resolution value, such as 1920x1080
+    fn resolution(&self) -> Result<(u32, u32), SyntaxError<'a>> {
+        self.value
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+            .ok_or_else(|| self.error("expected a resolution such as 1920x1080"))
+    }
+
+    /// The value of an attribute that may not be empty, such as a GROUP-ID
+    /// or a URI
+    fn non_empty(&self) -> Result<String, SyntaxError<'a>> {
+        if self.value.is_empty() {
+            return Err(self.error("expected a non-empty value"));
+        }
+        Ok(self.value.to_string())
+    }
+
+    fn yes_no(&self) -> Result<bool, SyntaxError<'a>> {
+        parse_yes_no(self.value).ok_or_else(|| self.error("expected YES or NO"))
+    }
+
+    fn video_range(&self, context: &mut Context<'a>) -> Result<VideoRange, SyntaxError<'a>> {
+        let video_range = VideoRange::from(self.value.to_string());
+        if let VideoRange::Other(_) = video_range {
+            context.unknown_value(self)?;
+        }
+        Ok(video_range)
+    }
+
+    fn into_extra(self, position: usize) -> ExtraAttribute {
+        ExtraAttribute {
+            name: self.name.to_string(),
+            value: self.value.to_string(),
+            quoted: self.quoted,
+            position,
+        }
+    }
+}
+
+/// Helper function to parse a comma separated attribute list. Text that is
+/// not an attribute is reported and skipped up to the next comma of its
+/// line. The list ends at the end of the line, or at text with no comma
+/// after it, which is returned for the caller to handle.
+fn parse_attribute_list<'a>(
+    mut input: &'a str,
+    context: &mut Context<'a>,
+) -> ParseResult<'a, Vec<Attribute<'a>>> {
+    let mut attributes: Vec<Attribute> = Vec::new();
+    // Where the list ends when nothing follows the last comma
+    let mut end = input;
+    loop {
+        let rest = match parse_attribute(input) {
+            // An attribute may only appear once in a list (RFC 8216 section
+            // 4.2), so a repeated one is ignored
+            Ok((rest, attribute)) if attributes.iter().any(|a| a.name == attribute.name) => {
+                let error = SyntaxError {
+                    attribute: Some(attribute.name),
+                    ..SyntaxError::new(attribute.name, "duplicate attribute")
+                };
+                context.recover(error, "ignored the repeated attribute")?;
+                rest
+            }
+            Ok((rest, attribute)) => {
+                attributes.push(attribute);
+                rest
+            }
+            Err(NomErr::Error(_)) => input,
+            Err(error) => return Err(error),
+        };
+        let parsed = rest.len() < input.len();
+
+        let (at, _) = space0(rest)?;
+        let end_of_line = at.is_empty() || at.starts_with(['\r', '\n']);
+        let comma = if parsed && at.starts_with(',') {
+            Some(at)
+        } else if end_of_line {
+            None
+        } else {
+            let comma = next_comma(at);
+            if comma.is_some() {
+                let error = SyntaxError::new(at, "expected an attribute");
+                context.recover(error, "skipped to the next attribute")?;
+            }
+            comma
+        };
+
+        match comma {
+            Some(comma) => {
+                end = comma;
+                input = &comma[1..];
+            }
+            None if end_of_line && !parsed => return Ok((end, attributes)),
+            None => return Ok((at, attributes)),
+        }
+    }
+}
+
+/// Helper function to parse a key-value pair
+fn parse_attribute(input: &str) -> ParseResult<'_, Attribute<'_>> {
+    map(
+        separated_pair(parse_key, tag("="), parse_quoted_or_unquoted_string),
+        |(name, (value, quoted))| Attribute {
+            name,
+            value,
+            quoted,
+        },
+    )(input)
+}
+
+/// The next comma of the line that is not inside a quoted string
+fn next_comma(input: &str) -> Option<&str> {
+    let mut quoted = false;
+    for (index, c) in input.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return Some(&input[index..]),
+            '\r' | '\n' => break,
+            _ => {}
+        }
+    }
+    None
+}
+
 /// Helper function to parse the key part of a key-value pair
-fn parse_key(input: &str) -> IResult<&str, String> {
+fn parse_key(input: &str) -> ParseResult<'_, &str> {
     let (input, _) = multispace0(input)?;
     let (input, key) =
         nom::bytes::complete::take_while1(|c: char| c.is_alphanumeric() || c == '-')(input)?;
     let (input, _) = multispace0(input)?;
-    Ok((input, key.to_string()))
+    Ok((input, key))
 }
 
-/// Helper function to parse either quoted or unquoted strings
-fn parse_quoted_or_unquoted_string(input: &str) -> IResult<&str, String> {
+/// Helper function to parse either quoted or unquoted strings, noting which it was
+fn parse_quoted_or_unquoted_string(input: &str) -> ParseResult<'_, (&str, bool)> {
     let (input, _) = multispace0(input)?;
     if input.starts_with('"') {
-        parse_quoted_string(input)
+        let (input, value) = parse_quoted_string(input)?;
+        Ok((input, (value, true)))
     } else {
-        parse_unquoted_string(input)
+        let (input, value) = parse_unquoted_string(input)?;
+        Ok((input, (value, false)))
     }
 }
 
-/// Parse quoted strings (surrounded by double quotes)
-fn parse_quoted_string(input: &str) -> IResult<&str, String> {
+/// Parse quoted strings (surrounded by double quotes), which may be empty
+fn parse_quoted_string(input: &str) -> ParseResult<'_, &str> {
     let (input, _) = tag("\"")(input)?;
-    let (input, value) = nom::bytes::complete::is_not("\"")(input)?;
+    let (input, value) = nom::bytes::complete::take_till(|c| c == '"')(input)?;
     let (input, _) = tag("\"")(input)?;
-    let (input, _) = multispace0(input)?;
-    Ok((input, value.trim().to_string()))
+    let (input, _) = space0(input)?;
+    Ok((input, value.trim()))
 }
 
 /// Parse unquoted strings (no quotes around them)
-fn parse_unquoted_string(input: &str) -> IResult<&str, String> {
-    // Parse any string until a comma or end of input
-    let (input, value) = nom::bytes::complete::is_not(",")(input)?;
-    Ok((input, value.trim().to_string()))
+fn parse_unquoted_string(input: &str) -> ParseResult<'_, &str> {
+    // Parse any string until a comma or end of line
+    let (input, value) = nom::bytes::complete::is_not(",\r\n")(input)?;
+    Ok((input, value.trim()))
 }
 
 #[cfg(test)]
@@ -473,25 +2695,506 @@ mod tests {
         round_trip_test(input, parse_media_track);
     }
 
+    #[test]
+    fn test_parse_media_track_all_attributes_round_trip() {
+        let subtitles = "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"Español (forzado)\",LANGUAGE=\"es-419\",ASSOC-LANGUAGE=\"es\",STABLE-RENDITION-ID=\"subs-es-forced\",DEFAULT=NO,AUTOSELECT=YES,FORCED=YES,CHARACTERISTICS=\"public.accessibility.transcribes-spoken-dialog\",URI=\"subs/es/forced.m3u8\"\n";
+        round_trip_test(subtitles, parse_media_track);
+
+        let captions = "#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES,AUTOSELECT=YES,INSTREAM-ID=\"SERVICE1\"\n";
+        round_trip_test(captions, parse_media_track);
+
+        let audio = "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"flac\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES,AUTOSELECT=YES,BIT-DEPTH=24,SAMPLE-RATE=96000,CHANNELS=\"2\",URI=\"audio/flac/vod.m3u8\"\n";
+        round_trip_test(audio, parse_media_track);
+
+        let track = parse_strict(audio, parse_media_track).unwrap();
+        assert_eq!(track.bit_depth, Some(24));
+        assert_eq!(track.sample_rate, Some(96000));
+    }
+
+    #[test]
+    fn test_parse_typed_enumerated_values() {
+        let track = parse_strict("#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"English\",DEFAULT=NO,AUTOSELECT=YES,INSTREAM-ID=\"CC1\"", parse_media_track).unwrap();
+        assert_eq!(track.track_type, MediaType::ClosedCaptions);
+        assert_eq!(track.default, Some(false));
+        assert_eq!(track.autoselect, Some(true));
+        assert_eq!(track.forced, None);
+
+        let variant = parse_strict(
+            "#EXT-X-STREAM-INF:BANDWIDTH=1,VIDEO-RANGE=HLG,CLOSED-CAPTIONS=NONE\nhlg.m3u8",
+            parse_stream_variant,
+        )
+        .unwrap();
+        assert_eq!(variant.video_range, Some(VideoRange::Hlg));
+        assert_eq!(variant.closed_captions, Some(ClosedCaptions::None));
+
+        // Unknown VIDEO-RANGE values are preserved as they are
+        let input = "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=1,VIDEO-RANGE=XDR,URI=\"xdr.m3u8\"";
+        round_trip_test(input, parse_iframe_stream);
+    }
+
+    #[test]
+    fn test_parse_invalid_enumerated_values() {
+        assert!(parse_strict(
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",NAME=\"a\",DEFAULT=yes",
+            parse_media_track
+        )
+        .is_err());
+        assert!(parse_strict(
+            "#EXT-X-MEDIA:TYPE=MUSIC,GROUP-ID=\"a\",NAME=\"a\"",
+            parse_media_track
+        )
+        .is_err());
+        assert!(parse_strict(
+            "#EXT-X-STREAM-INF:BANDWIDTH=1,CLOSED-CAPTIONS=cc\nv.m3u8",
+            parse_stream_variant
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let input = "#EXTM3U\n#EXT-X-VERSION:6\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=yes\n";
+        let error = match parse_playlist_with_options(input, ParseOptions::strict()) {
+            Err(PlaylistError::ParseError(error)) => error,
+            other => panic!("Expected a parse error but got {:?}", other.map(|_| ())),
+        };
+
+        assert_eq!((error.line, error.column, error.length), (3, 63, 3));
+        assert_eq!(error.tag.as_deref(), Some("#EXT-X-MEDIA"));
+        assert_eq!(error.attribute.as_deref(), Some("DEFAULT"));
+        assert_eq!(
+            error.to_string(),
+            "line 3, column 63, #EXT-X-MEDIA attribute DEFAULT: expected YES or NO\n  |\n3 | #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=yes\n  |                                                               ^^^"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_missing_attribute_and_header() {
+        let input = "#EXTM3U\r\n#EXT-X-SESSION-DATA:VALUE=\"no id\"\r\n";
+        match parse_playlist_with_options(input, ParseOptions::strict()) {
+            Err(PlaylistError::MissingAttribute(error)) => {
+                assert_eq!((error.line, error.column), (2, 21));
+                assert_eq!(error.tag.as_deref(), Some("#EXT-X-SESSION-DATA"));
+                assert_eq!(error.attribute.as_deref(), Some("DATA-ID"));
+                assert_eq!(error.source_line, "#EXT-X-SESSION-DATA:VALUE=\"no id\"");
+            }
+            other => panic!("Expected a parse error but got {:?}", other.map(|_| ())),
+        }
+
+        match parse_playlist_with_options("\n#EXT-X-VERSION:6\n", ParseOptions::strict()) {
+            Err(PlaylistError::ParseError(error)) => {
+                assert_eq!((error.line, error.column), (2, 1));
+                assert_eq!(error.tag, None);
+                assert_eq!(error.reason, "expected the playlist to start with #EXTM3U");
+            }
+            other => panic!("Expected a parse error but got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_parse_required_attributes() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\"
+#EXT-X-STREAM-INF:AUDIO=\"aac\"
+no-bandwidth.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1280000
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=80000
+#EXT-X-STREAM-INF:BANDWIDTH=5000000000
+prores.m3u8
+";
+        match parse_playlist_with_options(input, ParseOptions::strict()) {
+            Err(PlaylistError::MissingAttribute(error)) => {
+                assert_eq!(error.line, 2);
+                assert_eq!(error.attribute.as_deref(), Some("NAME"));
+            }
+            other => panic!(
+                "Expected a missing attribute but got {:?}",
+                other.map(|_| ())
+            ),
+        }
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.error.line,
+                    d.error.attribute.as_deref(),
+                    d.error.reason.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (2, Some("NAME"), "missing required attribute"),
+                (3, Some("BANDWIDTH"), "missing required attribute"),
+                (5, None, "expected a URI line after the tag"),
+                (6, Some("URI"), "missing required attribute"),
+            ]
+        );
+        assert!(playlist.media.is_empty());
+        assert!(playlist.frames.is_empty());
+        assert_eq!(playlist.variants.len(), 1);
+        assert_eq!(playlist.variants[0].bandwidth, 5_000_000_000);
+        assert_eq!(playlist.variants[0].uri, "prores.m3u8");
+
+        let input = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1280000\n";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::MissingUri(error)) if (error.line, error.column) == (2, 36)
+        ));
+    }
+
+    #[test]
+    fn test_lenient_parsing_diagnostics() {
+        let input = "#EXTM3U
+#EXT-X-SESSION-DATA:VALUE=\"no id\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=yes
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640by360,HDCP-LEVEL=TYPE-2,AUDIO=\"aac\"
+low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=lots
+high.m3u8
+";
+        assert!(parse_playlist_with_options(input, ParseOptions::strict()).is_err());
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.error.line,
+                    d.error.attribute.as_deref().unwrap(),
+                    d.recovery.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (2, "DATA-ID", "skipped the tag"),
+                (3, "DEFAULT", "kept the attribute as written"),
+                (4, "RESOLUTION", "kept the attribute as written"),
+                (4, "HDCP-LEVEL", "kept the unknown value"),
+                (6, "BANDWIDTH", "skipped the tag"),
+            ]
+        );
+
+        assert!(playlist.session_data.is_empty());
+        assert_eq!(playlist.media[0].default, None);
+        assert_eq!(playlist.variants.len(), 1);
+        assert_eq!(playlist.variants[0].resolution, None);
+        assert_eq!(
+            playlist.variants[0].hdcp_level,
+            Some(HdcpLevel::Other("TYPE-2".to_string()))
+        );
+        assert_eq!(
+            playlist.variants[0].to_string(),
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640by360,HDCP-LEVEL=TYPE-2,AUDIO=\"aac\"\nlow.m3u8"
+        );
+
+        let input = "#EXTM3U\n#EXT-X-VERSION:six\n";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error)) if error.tag.as_deref() == Some("#EXT-X-VERSION")
+        ));
+        assert!(parse_playlist(input).is_ok());
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        assert_eq!(playlist.version, None);
+        assert_eq!(diagnostics[0].error.line, 2);
+        assert_eq!(diagnostics[0].recovery, "ignored the tag");
+    }
+
+    #[test]
+    fn test_attribute_list_recovery() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,X_Y=1,AUDIO=\"aac\"
+low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2560000,CODECS=\"avc1.4d401f\" junk,AUDIO=\"aac\" more junk
+high.m3u8
+";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error)) if (error.line, error.column) == (2, 37)
+        ));
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.error.line, d.error.column, d.recovery.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (2, 37, "skipped to the next attribute"),
+                (4, 58, "skipped to the next attribute"),
+                (4, 75, "ignored the rest of the line"),
+            ]
+        );
+        assert_eq!(playlist.variants.len(), 2);
+        assert_eq!(playlist.variants[0].audio.as_deref(), Some("aac"));
+        assert_eq!(playlist.variants[1].audio.as_deref(), Some("aac"));
+        assert_eq!(playlist.variants[1].codecs.as_deref(), Some("avc1.4d401f"));
+    }
+
+    #[test]
+    fn test_duplicate_attributes() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1x2,RESOLUTION=3x4
+low.m3u8
+";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error))
+                if (error.line, error.column, error.length) == (2, 52, 10)
+        ));
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].error.attribute.as_deref(),
+            Some("RESOLUTION")
+        );
+        assert_eq!(diagnostics[0].recovery, "ignored the repeated attribute");
+        assert_eq!(playlist.variants[0].resolution, Some((1, 2)));
+    }
+
+    #[test]
+    fn test_empty_quoted_strings() {
+        let input = "#EXTM3U
+#EXT-X-SESSION-DATA:DATA-ID=\"com.example.title\",VALUE=\"\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"\",NAME=\"English\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"\",AUDIO=\"aac\"
+low.m3u8
+";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error)) if (error.line, error.column) == (3, 35)
+        ));
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.error.line,
+                    d.error.attribute.as_deref().unwrap(),
+                    d.recovery.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (3, "GROUP-ID", "skipped the tag"),
+                (4, "CODECS", "kept the attribute as written"),
+            ]
+        );
+        assert_eq!(playlist.session_data[0].value.as_deref(), Some(""));
+        assert!(playlist.media.is_empty());
+        assert_eq!(playlist.variants[0].codecs, None);
+        assert_eq!(playlist.variants[0].audio.as_deref(), Some("aac"));
+        assert_eq!(
+            playlist.variants[0].to_string(),
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"\",AUDIO=\"aac\"\nlow.m3u8"
+        );
+    }
+
+    #[test]
+    fn test_media_track_trailing_text() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"x\" trailing junk
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\"
+low.m3u8
+";
+        assert!(matches!(
+            parse_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error)) if (error.line, error.column) == (2, 49)
+        ));
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            (diagnostics[0].error.line, diagnostics[0].error.column),
+            (2, 49)
+        );
+        assert_eq!(diagnostics[0].recovery, "kept the text as its own line");
+        assert_eq!(playlist.media[0].name, "x");
+        assert_eq!(
+            playlist.items[1],
+            PlaylistItem::Other("trailing junk".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_stream_variant_round_trip() {
         let input = "#EXT-X-STREAM-INF:BANDWIDTH=2483789,AVERAGE-BANDWIDTH=1762745,CODECS=\"mp4a.40.2,hvc1.2.4.L90.90\",RESOLUTION=960x540,FRAME-RATE=23.97,VIDEO-RANGE=PQ,AUDIO=\"aac-128k\",CLOSED-CAPTIONS=NONE\nhdr10/unenc/1650k/vod.m3u8";
         round_trip_test(input, parse_stream_variant);
     }
 
+    #[test]
+    fn test_parse_stream_variant_all_attributes_round_trip() {
+        let input = "#EXT-X-STREAM-INF:PROGRAM-ID=1,BANDWIDTH=9533270,AVERAGE-BANDWIDTH=6100000,SCORE=1.5,CODECS=\"dvh1.08.07,ec-3\",SUPPLEMENTAL-CODECS=\"dvh1.08.07/db4h\",RESOLUTION=3840x2160,FRAME-RATE=23.976,HDCP-LEVEL=TYPE-1,ALLOWED-CPC=\"com.example.drm:SW,HW\",VIDEO-RANGE=PQ,REQ-VIDEO-LAYOUT=\"CH-STEREO,CH-MONO\",STABLE-VARIANT-ID=\"uhd-dv\",AUDIO=\"eac3\",VIDEO=\"angles\",SUBTITLES=\"subs\",CLOSED-CAPTIONS=\"cc\",PATHWAY-ID=\"CDN-A\"\ndv/6000k/vod.m3u8";
+        round_trip_test(input, parse_stream_variant);
+
+        let variant = parse_strict(input, parse_stream_variant).unwrap();
+        assert_eq!(variant.program_id, Some(1));
+        assert_eq!(variant.score, Some(1.5));
+        assert_eq!(variant.hdcp_level, Some(HdcpLevel::Type1));
+        assert_eq!(variant.stable_variant_id.as_deref(), Some("uhd-dv"));
+        assert_eq!(variant.pathway_id.as_deref(), Some("CDN-A"));
+        assert_eq!(
+            variant.closed_captions,
+            Some(ClosedCaptions::GroupId("cc".to_string()))
+        );
+        assert!(variant.extra_attributes.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_attributes_keep_their_text() {
+        let input = "#EXT-X-STREAM-INF:BANDWIDTH=1280000,FRAME-RATE=30.000\nlow/vod.m3u8";
+        round_trip_test(input, parse_stream_variant);
+
+        let (_, variant) = parse_stream_variant(input, &mut Context::new(ParseOptions::strict()))
+            .expect("Failed to parse variant");
+        let mut variant = variant.expect("Expected the variant not to be skipped");
+        variant.frame_rate = Some(25.0);
+        assert_eq!(
+            variant.to_string(),
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,FRAME-RATE=25\nlow/vod.m3u8"
+        );
+    }
+
     #[test]
     fn test_parse_iframe_stream_round_trip() {
         let input = "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=222552,CODECS=\"hvc1.2.4.L93.90\",RESOLUTION=1280x720,VIDEO-RANGE=PQ,URI=\"hdr10/unenc/3300k/vod-iframe.m3u8\"";
         round_trip_test(input, parse_iframe_stream);
     }
 
-    fn round_trip_test<T, F>(input: &str, parser: F)
+    #[test]
+    fn test_extra_attributes_round_trip() {
+        let media = "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac-128k\",X-CUSTOM=\"a,b\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES,AUTOSELECT=YES,CHANNELS=\"2\",URI=\"audio/unenc/aac_128k/vod.m3u8\",X-TRAILING=7\n";
+        round_trip_test(media, parse_media_track);
+
+        let variant = "#EXT-X-STREAM-INF:X-FIRST=1,BANDWIDTH=2483789,AVERAGE-BANDWIDTH=1762745,CODECS=\"mp4a.40.2,hvc1.2.4.L90.90\",RESOLUTION=960x540,FRAME-RATE=23.97,X-VENDOR=\"ladder-a\",VIDEO-RANGE=PQ,AUDIO=\"aac-128k\",CLOSED-CAPTIONS=NONE\nhdr10/unenc/1650k/vod.m3u8";
+        round_trip_test(variant, parse_stream_variant);
+
+        let iframe = "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=222552,CODECS=\"hvc1.2.4.L93.90\",RESOLUTION=1280x720,X-CUSTOM=ON,VIDEO-RANGE=PQ,URI=\"hdr10/unenc/3300k/vod-iframe.m3u8\"";
+        round_trip_test(iframe, parse_iframe_stream);
+
+        let track = parse_strict(media, parse_media_track).unwrap();
+        assert_eq!(
+            track.extra_attributes[0],
+            ExtraAttribute {
+                name: "X-CUSTOM".to_string(),
+                value: "a,b".to_string(),
+                quoted: true,
+                position: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_session_tags_round_trip() {
+        let session_data =
+            "#EXT-X-SESSION-DATA:DATA-ID=\"com.example.title\",VALUE=\"Example\",LANGUAGE=\"en\"";
+        round_trip_test(session_data, parse_session_data);
+
+        let session_data_uri =
+            "#EXT-X-SESSION-DATA:DATA-ID=\"com.example.lyrics\",URI=\"lyrics.json\",FORMAT=JSON";
+        round_trip_test(session_data_uri, parse_session_data);
+
+        let session_key = "#EXT-X-SESSION-KEY:METHOD=SAMPLE-AES,URI=\"skd://key-1\",IV=0x1234567890ABCDEF1234567890ABCDEF,KEYFORMAT=\"com.apple.streamingkeydelivery\",KEYFORMATVERSIONS=\"1\"";
+        round_trip_test(session_key, parse_session_key);
+
+        let start = "#EXT-X-START:TIME-OFFSET=-12.5,PRECISE=YES";
+        round_trip_test(start, parse_start);
+
+        let key = parse_strict(session_key, parse_session_key).unwrap();
+        assert_eq!(key.method, KeyMethod::SampleAes);
+
+        assert!(parse_strict("#EXT-X-SESSION-DATA:VALUE=\"no id\"", parse_session_data).is_err());
+        assert!(parse_strict("#EXT-X-START:PRECISE=YES", parse_start).is_err());
+    }
+
+    #[test]
+    fn test_parse_master_playlist_session_tags() {
+        let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        file_path.push("tests/data/unknown_tags_parse_test.m3u8");
+
+        let input = fs::read_to_string(file_path).expect("Failed to read test file");
+        let playlist = parse_playlist(&input).expect("Failed to parse playlist");
+
+        assert_eq!(playlist.version, Some(6));
+        assert_eq!(playlist.session_data.len(), 1);
+        assert_eq!(playlist.session_data[0].data_id, "com.example.title");
+        assert!(!playlist.items.iter().any(
+            |item| matches!(item, PlaylistItem::Tag(tag) if tag.starts_with("#EXT-X-SESSION"))
+        ));
+    }
+
+    #[test]
+    fn test_parse_content_steering_round_trip() {
+        round_trip_test(
+            "#EXT-X-CONTENT-STEERING:SERVER-URI=\"https://steering.example.com/manifest.json\",PATHWAY-ID=\"CDN-A\"",
+            parse_content_steering,
+        );
+        round_trip_test(
+            "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=222552,CODECS=\"hvc1.2.4.L93.90\",RESOLUTION=1280x720,VIDEO-RANGE=PQ,PATHWAY-ID=\"CDN-A\",URI=\"https://cdn-a.example.com/3300k/vod-iframe.m3u8\"",
+            parse_iframe_stream,
+        );
+        assert!(parse_strict(
+            "#EXT-X-CONTENT-STEERING:PATHWAY-ID=\"CDN-A\"",
+            parse_content_steering
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_define_round_trip() {
+        round_trip_test(
+            "#EXT-X-DEFINE:NAME=\"cdn\",VALUE=\"https://cdn-a.example.com\"",
+            parse_define,
+        );
+        round_trip_test("#EXT-X-DEFINE:QUERYPARAM=\"token\"", parse_define);
+        round_trip_test("#EXT-X-DEFINE:IMPORT=\"cdn\"", parse_define);
+        round_trip_test(
+            "#EXT-X-DEFINE:X-SCOPE=session,NAME=\"cdn\",VALUE=\"a\",X-NOTE=\"b\"",
+            parse_define,
+        );
+
+        assert!(parse_strict("#EXT-X-DEFINE:NAME=\"cdn\"", parse_define).is_err());
+        assert!(parse_strict(
+            "#EXT-X-DEFINE:NAME=\"a\",VALUE=\"b\",IMPORT=\"c\"",
+            parse_define
+        )
+        .is_err());
+    }
+
+    /// Runs an element parser in strict mode
+    fn parse_strict<'a, T>(input: &'a str, parser: ElementParser<'a, T>) -> Result<T, String> {
+        match parser(input, &mut Context::new(ParseOptions::strict())) {
+            Ok((_, element)) => Ok(element.expect("Strict parsing never skips an element")),
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    type ElementParser<'a, T> = fn(&'a str, &mut Context<'a>) -> ParseResult<'a, Option<T>>;
+
+    fn round_trip_test<'a, T>(input: &'a str, parser: ElementParser<'a, T>)
     where
         T: std::fmt::Display + std::fmt::Debug,
-        F: Fn(&str) -> IResult<&str, T>,
     {
-        // Step 1: Parse the input string
-        let result = parser(input);
+        // Step 1: Parse the input string, in lenient mode
+        let result = parser(input, &mut Context::new(ParseOptions::default()));
         assert!(
             result.is_ok(),
             "Expected successful parsing but got error: {:?}",
@@ -499,6 +3202,7 @@ mod tests {
         );
 
         let (_, parsed_object) = result.unwrap();
+        let parsed_object = parsed_object.expect("Expected the element not to be skipped");
 
         // Step 2: Serialize the parsed object back to a string using the Display trait
         let serialized_output = format!("{}", parsed_object);
@@ -512,8 +3216,163 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_media_playlist_round_trip() {
+        let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        file_path.push("tests/data/media_parse_test.m3u8");
+
+        let input = fs::read_to_string(file_path).expect("Failed to read test file");
+
+        let playlist = parse_media_playlist(&input).expect("Failed to parse media playlist");
+        assert_eq!(playlist.version, Some(7));
+        assert_eq!(playlist.target_duration, Some(6));
+        assert_eq!(playlist.media_sequence, Some(0));
+        assert_eq!(playlist.playlist_type, Some(PlaylistType::Vod));
+        assert!(playlist.end_list);
+        assert_eq!(playlist.segments.len(), 5);
+        assert_eq!(playlist.segments[1].duration, 6.0);
+        assert_eq!(playlist.segments[4].duration, 3.003);
+        assert_eq!(playlist.segments[4].uri, "segment_4.m4s");
+        assert_eq!(
+            playlist.items[12],
+            MediaPlaylistItem::Tag("#EXT-X-DISCONTINUITY".to_string())
+        );
+
+        let mut serialized_output = Vec::new();
+        playlist
+            .write_to(&mut serialized_output)
+            .expect("Failed to serialize playlist");
+        let serialized_str = String::from_utf8(serialized_output)
+            .expect("Failed to convert serialized output to string");
+
+        assert_eq!(serialized_str, input);
+    }
+
+    #[test]
+    fn test_media_playlist_segment_uris() {
+        let input = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+orphan.m4s
+#EXTINF:6.006,
+#EXTINF:6.006,
+segment_0.m4s
+#EXTINF:6.006,
+";
+        assert!(matches!(
+            parse_media_playlist_with_options(input, ParseOptions::strict()),
+            Err(PlaylistError::ParseError(error)) if error.line == 3
+        ));
+        let strict = parse_media_playlist_with_options(
+            "#EXTM3U\n#EXTINF:6.006,\n#EXT-X-ENDLIST\n",
+            ParseOptions::strict(),
+        );
+        assert!(matches!(
+            strict,
+            Err(PlaylistError::MissingSegmentUri(ref error)) if (error.line, error.column) == (2, 15)
+        ));
+        assert_eq!(
+            strict.unwrap_err().to_string(),
+            "Missing the URI line of the media segment at line 2, column 15, #EXTINF: expected a URI line after the tag\n  |\n2 | #EXTINF:6.006,\n  |               ^"
+        );
+
+        let (playlist, diagnostics) =
+            parse_media_playlist_with_options(input, ParseOptions::default())
+                .expect("Lenient parsing should recover");
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.error.line, d.recovery.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (3, "kept the line as written"),
+                (4, "skipped the tag"),
+                (7, "skipped the tag"),
+            ]
+        );
+        assert_eq!(playlist.segments.len(), 1);
+        assert_eq!(playlist.segments[0].uri, "segment_0.m4s");
+        assert_eq!(
+            playlist.items[1],
+            MediaPlaylistItem::Other("orphan.m4s".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_any_playlist_detects_kind() {
+        let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1280000\nlow/vod.m3u8\n";
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:9.009,\nsegment_0.ts\n";
+
+        assert!(matches!(
+            parse_any_playlist(master),
+            Ok(Playlist::Master(ref p)) if p.variants.len() == 1
+        ));
+        assert!(matches!(
+            parse_any_playlist(media),
+            Ok(Playlist::Media(ref p)) if p.segments.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let master = "#EXTM3U
+
+
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"en.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\"
+low/vod.m3u8
+# A comment between variants
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"French\",URI=\"fr.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=2560000,AUDIO=\"aac\"
+high/vod.m3u8
+
+
+
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,URI=\"low/iframe.m3u8\"
+";
+        let media = "#EXTM3U
+
+#EXT-X-TARGETDURATION:6
+
+
+#EXTINF:6.006,
+segment_0.m4s
+# A comment between segments
+#EXTINF:6.006,
+segment_1.m4s
+#EXT-X-ENDLIST
+";
+        let variants = |input: &str| {
+            [
+                input.to_string(),
+                input.trim_end().to_string(),
+                input.replace('\n', "\r\n"),
+                input.trim_end().replace('\n', "\r\n"),
+            ]
+        };
+
+        for input in variants(master) {
+            let playlist = parse_playlist(&input).expect("Failed to parse master playlist");
+            let mut output = Vec::new();
+            playlist.write_to(&mut output).expect("Failed to write");
+            assert_eq!(String::from_utf8(output).unwrap(), input);
+        }
+        for input in variants(media) {
+            let playlist = parse_media_playlist(&input).expect("Failed to parse media playlist");
+            let mut output = Vec::new();
+            playlist.write_to(&mut output).expect("Failed to write");
+            assert_eq!(String::from_utf8(output).unwrap(), input);
+        }
+    }
+
     fn get_test_files() -> Vec<&'static str> {
-        vec!["parse_test.m3u8"]
+        vec![
+            "parse_test.m3u8",
+            "master_unenc_hdr10_all.m3u8",
+            "unknown_tags_parse_test.m3u8",
+            "attribute_order_parse_test.m3u8",
+            "number_format_parse_test.m3u8",
+        ]
     }
 
     #[test]
@@ -547,13 +3406,11 @@ mod tests {
             let serialized_str = String::from_utf8(serialized_output)
                 .expect("Failed to convert serialized output to string");
 
-            // Step 4: Ensure the serialized string matches the original input (ignoring differences like newlines)
+            // Step 4: Ensure the serialized string matches the original input byte for byte
             assert_eq!(
-                serialized_str.trim(),
-                input.trim(),
+                serialized_str, input,
                 "Expected serialized output to match the input for {}, but got:\n{}",
-                file_name,
-                serialized_str
+                file_name, serialized_str
             );
         }
     }
@@ -0,0 +1,409 @@
+//! This module provides sort profiles: declarative descriptions of how to filter, rewrite and
+//! sort an M3U8 master playlist, loaded from a TOML or YAML file. A file holds any number of
+//! named profiles, so that the settings of every customer of a pipeline can live side by side.
+//!
+//! ```toml
+//! default = "customer-a"
+//!
+//! [profiles.customer-a]
+//! sort-stream-by = ["video-codec", "bandwidth:desc"]
+//! codec-order = ["hevc", "avc"]
+//! group-media-by = "variants"
+//! align-iframes = "attributes"
+//!
+//! [profiles.customer-a.filters]
+//! max-bandwidth = 8000000
+//! languages = ["en", "es"]
+//!
+//! [[profiles.customer-a.rewrites]]
+//! find = "http://"
+//! replace = "https://"
+//! ```
+
+use crate::align::{IFrameAlignment, IFrameMatch};
+use crate::codecs::VideoCodecFamily;
+use crate::errors::PlaylistError;
+use crate::parser::MasterPlaylist;
+use crate::sort::{
+    language_matches, GroupOrder, Nulls, ResolutionOrder, SortIFrameBy, SortKey, SortMediaBy,
+    SortOptions, SortStreamBy,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::{fs, path::Path};
+
+/// A file of named sort profiles.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SortProfiles {
+    /// The name of the profile used when none is selected.
+    #[serde(default)]
+    pub default: Option<String>,
+    /// The profiles, by name.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SortProfile>,
+}
+
+/// How to filter, rewrite and sort a playlist. The settings mirror the
+/// flags of the CLI. Elements with no sort keys keep their order.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SortProfile {
+    pub sort_stream_by: Vec<SortKey<SortStreamBy>>,
+    pub sort_media_by: Vec<SortKey<SortMediaBy>>,
+    pub sort_iframe_by: Vec<SortKey<SortIFrameBy>>,
+    pub codec_order: Vec<VideoCodecFamily>,
+    pub nulls: Nulls,
+    pub resolution_order: ResolutionOrder,
+    pub preferred_languages: Vec<String>,
+    pub group_media_by: Option<GroupOrder>,
+    pub pin_default: bool,
+    pub align_iframes: Option<IFrameMatch>,
+    pub filters: Filters,
+    /// Applied in order to the URIs of the variant streams, renditions and
+    /// I-frame streams.
+    pub rewrites: Vec<Rewrite>,
+}
+
+/// The elements a profile removes from a playlist, before sorting. Unset
+/// filters remove nothing.
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Filters {
+    /// Removes the variant streams with a lower BANDWIDTH.
+    pub min_bandwidth: Option<u64>,
+    /// Removes the variant streams with a higher BANDWIDTH.
+    pub max_bandwidth: Option<u64>,
+    /// Removes the variant streams and I-frame streams with a taller
+    /// RESOLUTION.
+    pub max_height: Option<u32>,
+    /// Keeps only the variant streams and I-frame streams of these video
+    /// codec families. Streams without a known video codec are kept.
+    pub video_codecs: Vec<VideoCodecFamily>,
+    /// Keeps only the renditions with a LANGUAGE one of these BCP 47
+    /// language ranges matches. Renditions without a LANGUAGE are kept.
+    pub languages: Vec<String>,
+    /// Keeps only the variant streams and I-frame streams of this pathway.
+    pub pathway: Option<String>,
+}
+
+/// A replacement of text in URIs, such as moving the streams to another
+/// host.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rewrite {
+    /// The text to find.
+    pub find: String,
+    /// The text to replace every occurrence with.
+    pub replace: String,
+}
+
+impl SortProfiles {
+    /// Parses a file of profiles from TOML
+    pub fn from_toml(input: &str) -> Result<Self, PlaylistError> {
+        toml::from_str::<Self>(input)
+            .map_err(|err| PlaylistError::ProfileError(err.to_string()))?
+            .checked()
+    }
+
+    /// Parses a file of profiles from YAML
+    pub fn from_yaml(input: &str) -> Result<Self, PlaylistError> {
+        serde_yaml::from_str::<Self>(input)
+            .map_err(|err| PlaylistError::ProfileError(err.to_string()))?
+            .checked()
+    }
+
+    /// Reads a file of profiles, as YAML if its extension is `.yaml` or
+    /// `.yml`, and as TOML otherwise
+    pub fn load(path: &Path) -> Result<Self, PlaylistError> {
+        let input = fs::read_to_string(path)
+            .map_err(|err| PlaylistError::ProfileError(format!("{}: {}", path.display(), err)))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => Self::from_yaml(&input),
+            _ => Self::from_toml(&input),
+        }
+    }
+
+    /// Selects a profile by name. Without a name, selects the default
+    /// profile, or the only profile of the file.
+    pub fn get(&self, name: Option<&str>) -> Result<&SortProfile, PlaylistError> {
+        let name = match (name, self.default.as_deref()) {
+            (Some(name), _) | (None, Some(name)) => name,
+            (None, None) if self.profiles.len() == 1 => {
+                return Ok(self.profiles.values().next().expect("One profile"));
+            }
+            (None, None) => {
+                return Err(PlaylistError::ProfileError(format!(
+                    "no default profile, select one of {}",
+                    self.names()
+                )));
+            }
+        };
+        self.profiles.get(name).ok_or_else(|| {
+            PlaylistError::ProfileError(format!(
+                "unknown profile \"{}\", expected one of {}",
+                name,
+                self.names()
+            ))
+        })
+    }
+
+    /// Rejects a default that names no profile
+    fn checked(self) -> Result<Self, PlaylistError> {
+        match self.default {
+            Some(ref name) if !self.profiles.contains_key(name) => Err(
+                PlaylistError::ProfileError(format!("unknown default profile \"{}\"", name)),
+            ),
+            _ => Ok(self),
+        }
+    }
+
+    /// The names of the profiles, quoted and separated by commas
+    fn names(&self) -> String {
+        self.profiles
+            .keys()
+            .map(|name| format!("\"{}\"", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl SortProfile {
+    /// Reads a file of profiles and selects one, as `SortProfiles::get`
+    /// does
+    pub fn load(path: &Path, name: Option<&str>) -> Result<Self, PlaylistError> {
+        SortProfiles::load(path)?.get(name).cloned()
+    }
+
+    /// The options the sort keys of the profile compare with
+    pub fn sort_options(&self) -> SortOptions {
+        SortOptions {
+            codec_order: self.codec_order.clone(),
+            nulls: self.nulls,
+            resolution_order: self.resolution_order,
+            language_preferences: self.preferred_languages.clone(),
+            media_groups: self.group_media_by,
+            pin_default: self.pin_default,
+        }
+    }
+}
+
+impl MasterPlaylist {
+    /// Applies a profile: removes the elements its filters reject, rewrites
+    /// the URIs, sorts the variant streams, renditions and I-frame streams,
+    /// and then aligns the I-frame streams with the variant streams if the
+    /// profile asks to, returning the streams left without a counterpart.
+    pub fn apply_profile(&mut self, profile: &SortProfile) -> Option<IFrameAlignment> {
+        self.apply_filters(&profile.filters);
+        for rewrite in &profile.rewrites {
+            self.apply_rewrite(rewrite);
+        }
+
+        let options = profile.sort_options();
+        self.sort_stream_with_options(&profile.sort_stream_by, &options);
+        self.sort_media_with_options(&profile.sort_media_by, &options);
+        self.sort_iframe_with_options(&profile.sort_iframe_by, &options);
+
+        profile.align_iframes.map(|by| self.align_iframes(by))
+    }
+
+    /// Removes the variant streams, renditions and I-frame streams the
+    /// filters reject
+    pub fn apply_filters(&mut self, filters: &Filters) {
+        if let Some(ref pathway_id) = filters.pathway {
+            self.retain_pathway(pathway_id);
+        }
+
+        let codec_allowed = |family: Option<VideoCodecFamily>| match family {
+            Some(family) if !filters.video_codecs.is_empty() => {
+                filters.video_codecs.contains(&family)
+            }
+            _ => true,
+        };
+        let height_allowed = |resolution: Option<(u32, u32)>| {
+            resolution
+                .zip(filters.max_height)
+                .is_none_or(|((_, height), max_height)| height <= max_height)
+        };
+
+        self.retain_variants(|variant| {
+            filters
+                .min_bandwidth
+                .is_none_or(|min| variant.bandwidth >= min)
+                && filters
+                    .max_bandwidth
+                    .is_none_or(|max| variant.bandwidth <= max)
+                && height_allowed(variant.resolution)
+                && codec_allowed(variant.video_codec().and_then(|codec| codec.video_family()))
+        });
+        self.frames.retain(|frame| {
+            height_allowed(frame.resolution)
+                && codec_allowed(frame.video_codec().and_then(|codec| codec.video_family()))
+        });
+
+        if !filters.languages.is_empty() {
+            self.media.retain(|track| match track.language {
+                Some(ref language) => filters
+                    .languages
+                    .iter()
+                    .any(|range| language_matches(range, language)),
+                None => true,
+            });
+        }
+    }
+
+    /// Replaces text in the URIs of the variant streams, renditions and
+    /// I-frame streams
+    pub fn apply_rewrite(&mut self, rewrite: &Rewrite) {
+        let replace = |uri: &mut String| *uri = uri.replace(&rewrite.find, &rewrite.replace);
+
+        self.variants
+            .iter_mut()
+            .for_each(|variant| replace(&mut variant.uri));
+        self.media
+            .iter_mut()
+            .filter_map(|track| track.uri.as_mut())
+            .for_each(replace);
+        self.frames
+            .iter_mut()
+            .for_each(|frame| replace(&mut frame.uri));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_playlist;
+    use crate::sort::Direction;
+
+    const PROFILES: &str = r#"
+default = "web"
+
+[profiles.web]
+sort-stream-by = ["video-codec", "bandwidth:desc"]
+codec-order = ["hevc", "avc"]
+sort-media-by = ["language-preference"]
+preferred-languages = ["es", "en"]
+align-iframes = "attributes"
+
+[profiles.web.filters]
+max-height = 1080
+languages = ["en", "es"]
+
+[[profiles.web.rewrites]]
+find = "http://cdn-a.example.com/"
+replace = "https://cdn-b.example.com/"
+
+[profiles.tv]
+sort-stream-by = ["bandwidth"]
+nulls = "last"
+"#;
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",LANGUAGE=\"en\",URI=\"http://cdn-a.example.com/en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Deutsch\",LANGUAGE=\"de\",URI=\"http://cdn-a.example.com/de.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Español\",LANGUAGE=\"es-MX\",URI=\"http://cdn-a.example.com/es.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=640x360,AUDIO=\"aac\"
+http://cdn-a.example.com/low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=7680000,CODECS=\"hvc1.2.4.L150.B0,mp4a.40.2\",RESOLUTION=1920x1080,AUDIO=\"aac\"
+http://cdn-a.example.com/hevc.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=15360000,CODECS=\"hvc1.2.4.L153.B0,mp4a.40.2\",RESOLUTION=3840x2160,AUDIO=\"aac\"
+http://cdn-a.example.com/uhd.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2560000,CODECS=\"avc1.4d401f,mp4a.40.2\",RESOLUTION=1280x720,AUDIO=\"aac\"
+http://cdn-a.example.com/mid.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,CODECS=\"avc1.4d401e\",RESOLUTION=640x360,URI=\"http://cdn-a.example.com/low-iframe.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=600000,CODECS=\"hvc1.2.4.L153.B0\",RESOLUTION=3840x2160,URI=\"http://cdn-a.example.com/uhd-iframe.m3u8\"
+";
+
+    #[test]
+    fn test_select_profiles() {
+        let profiles = SortProfiles::from_toml(PROFILES).expect("Failed to parse profiles");
+
+        let web = profiles.get(None).unwrap();
+        assert_eq!(web.sort_stream_by.len(), 2);
+        assert_eq!(web.sort_stream_by[1].direction, Direction::Descending);
+        assert_eq!(web.filters.max_height, Some(1080));
+
+        let tv = profiles.get(Some("tv")).unwrap();
+        assert_eq!(tv.nulls, Nulls::Last);
+        assert!(tv.rewrites.is_empty());
+
+        assert!(matches!(
+            profiles.get(Some("radio")),
+            Err(PlaylistError::ProfileError(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_invalid_profiles() {
+        for input in [
+            "default = \"radio\"\n[profiles.tv]\n",
+            "[profiles.tv]\nsort-stream-by = [\"bandwidth:up\"]\n",
+            "[profiles.tv]\nsort-by = [\"bandwidth\"]\n",
+        ] {
+            assert!(matches!(
+                SortProfiles::from_toml(input),
+                Err(PlaylistError::ProfileError(_))
+            ));
+        }
+
+        let profiles = SortProfiles::from_toml("[profiles.tv]\n[profiles.web]\n").unwrap();
+        assert!(profiles.get(None).is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_profiles() {
+        let profiles = SortProfiles::from_yaml(
+            "profiles:
+  tv:
+    sort-stream-by: [resolution:desc]
+    group-media-by: variants
+    filters:
+      video-codecs: [avc]
+    rewrites:
+      - find: low
+        replace: high
+",
+        )
+        .expect("Failed to parse profiles");
+        let tv = profiles.get(None).unwrap();
+
+        assert_eq!(tv.group_media_by, Some(GroupOrder::Variants));
+        assert_eq!(tv.filters.video_codecs, [VideoCodecFamily::Avc]);
+        assert_eq!(tv.rewrites[0].replace, "high");
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let profiles = SortProfiles::from_toml(PROFILES).expect("Failed to parse profiles");
+        let mut playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        let alignment = playlist.apply_profile(profiles.get(Some("web")).unwrap());
+
+        let variants: Vec<_> = playlist.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(
+            variants,
+            [
+                "https://cdn-b.example.com/hevc.m3u8",
+                "https://cdn-b.example.com/mid.m3u8",
+                "https://cdn-b.example.com/low.m3u8",
+            ]
+        );
+        let languages: Vec<_> = playlist
+            .media
+            .iter()
+            .map(|track| track.language.as_deref().unwrap())
+            .collect();
+        assert_eq!(languages, ["es-MX", "en"]);
+
+        let alignment = alignment.expect("The profile aligns the I-frame streams");
+        assert_eq!(
+            alignment.unmatched_variants,
+            [
+                "https://cdn-b.example.com/hevc.m3u8",
+                "https://cdn-b.example.com/mid.m3u8"
+            ]
+        );
+        assert!(alignment.unmatched_frames.is_empty());
+        assert_eq!(playlist.frames.len(), 1);
+    }
+}
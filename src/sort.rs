@@ -1,10 +1,21 @@
 //! This module provides sorting functionalities for M3U8 Master Playlists,
 //! allowing sorting of streams, media tracks, and I-frame streams by
-//! various criteria. The sorting is done by primary and secondary sorting
-//! criteria defined by enum variants.
+//! various criteria. The sorting is done by any number of `SortKey`s in
+//! order of precedence, each made of a criterion defined by an enum variant,
+//! a direction, and where to put elements that lack the attribute.
+//!
+//! The codec criteria decode the CODECS attribute, so that streams can be
+//! grouped by video codec family and then ordered by level. The order of
+//! the families is set through `SortOptions`.
 
+use crate::codecs::{AudioCodecFamily, Codec, Tier, VideoCodecFamily};
 use crate::parser::MasterPlaylist;
-use crate::parser::{IFrameStream, MediaTrack, StreamVariant};
+use crate::parser::{IFrameStream, MediaTrack, MediaType, StreamVariant};
+use clap::ValueEnum;
+use serde::{de, Deserialize, Deserializer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
 
 /// Specifies sorting criteria for stream variants in a playlist.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug, Default)]
@@ -19,6 +30,20 @@ pub enum SortStreamBy {
     Audio,
     ClosedCaptions,
     Uri,
+    ProgramId,
+    Score,
+    SupplementalCodecs,
+    HdcpLevel,
+    AllowedCpc,
+    ReqVideoLayout,
+    StableVariantId,
+    Video,
+    Subtitles,
+    PathwayId,
+    VideoCodec,
+    VideoCodecLevel,
+    AudioCodec,
+    DolbyVisionProfile,
 }
 
 /// Specifies sorting criteria for media tracks in a playlist.
@@ -33,6 +58,14 @@ pub enum SortMediaBy {
     AutoSelect,
     Channels,
     Uri,
+    AssocLanguage,
+    StableRenditionId,
+    Forced,
+    InstreamId,
+    BitDepth,
+    SampleRate,
+    Characteristics,
+    LanguagePreference,
 }
 
 /// Specifies sorting criteria for I-frame streams in a playlist.
@@ -44,112 +77,724 @@ pub enum SortIFrameBy {
     Resolution,
     VideoRange,
     Uri,
+    VideoCodec,
+    VideoCodecLevel,
+    DolbyVisionProfile,
 }
 
-impl MasterPlaylist {
-    /// Sorts the stream variants within the playlist using primary and
-    /// secondary sorting criteria.
-    pub fn sort_stream(&mut self, sort_by: (SortStreamBy, SortStreamBy)) {
-        self.variants.sort_by(|a, b| {
-            let primary_cmp = Self::compare_stream(a, b, sort_by.0);
-            if primary_cmp == std::cmp::Ordering::Equal {
-                Self::compare_stream(a, b, sort_by.1)
-            } else {
-                primary_cmp
+/// Settings that change how some sorting criteria compare.
+#[derive(Clone, Debug, Default)]
+pub struct SortOptions {
+    /// The order of video codec families for the `VideoCodec` criteria.
+    /// Families not listed sort after the listed ones, in their default
+    /// order. Streams without a known video codec are missing the value.
+    pub codec_order: Vec<VideoCodecFamily>,
+    /// Where elements that lack the attribute of a sorting criterion go,
+    /// for the keys that do not set it themselves.
+    pub nulls: Nulls,
+    /// How the `Resolution` criteria compare resolutions.
+    pub resolution_order: ResolutionOrder,
+    /// The BCP 47 language ranges of the `LanguagePreference` criteria, most
+    /// preferred first, such as `en-US`, `en`, `es`.
+    pub language_preferences: Vec<String>,
+    /// When set, media tracks are sorted group by group: the groups are
+    /// ordered relative to each other, and the keys order the renditions
+    /// inside each group.
+    pub media_groups: Option<GroupOrder>,
+    /// Whether the DEFAULT=YES rendition of each group goes first in it,
+    /// ahead of the keys, when sorting group by group.
+    pub pin_default: bool,
+}
+
+/// How rendition groups are ordered relative to each other.
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupOrder {
+    /// By GROUP-ID.
+    GroupId,
+    /// By the lowest BANDWIDTH of the variant streams that reference the
+    /// group. Groups no variant stream references go last.
+    Bandwidth,
+    /// By the position of their first rendition.
+    FirstAppearance,
+    /// By the position of the first variant stream that references the
+    /// group, so that the groups follow the order of the variant streams.
+    /// Groups no variant stream references go last.
+    Variants,
+}
+
+/// How resolutions compare.
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolutionOrder {
+    /// By width, then by height.
+    #[default]
+    WidthFirst,
+    /// By height, then by width.
+    HeightFirst,
+    /// By pixel count, then by height.
+    Area,
+}
+
+impl SortOptions {
+    /// The position of the first language preference that matches a
+    /// LANGUAGE. Languages that match none, and tracks without one, rank
+    /// after every match.
+    fn language_rank(&self, language: Option<&String>) -> usize {
+        language
+            .and_then(|language| {
+                self.language_preferences
+                    .iter()
+                    .position(|range| language_matches(range, language))
+            })
+            .unwrap_or(self.language_preferences.len())
+    }
+
+    /// The value a resolution is compared by.
+    fn resolution_key(&self, resolution: Option<(u32, u32)>) -> Option<(u64, u64)> {
+        let (width, height) = resolution.map(|(w, h)| (w as u64, h as u64))?;
+        Some(match self.resolution_order {
+            ResolutionOrder::WidthFirst => (width, height),
+            ResolutionOrder::HeightFirst => (height, width),
+            ResolutionOrder::Area => (width * height, height),
+        })
+    }
+
+    /// The rank of the video codec family of a stream, if it has one.
+    fn codec_rank(&self, codec: Option<Codec>) -> Option<usize> {
+        let family = codec?.video_family()?;
+        Some(match self.codec_order.iter().position(|&f| f == family) {
+            Some(position) => position,
+            None => self.codec_order.len() + family as usize,
+        })
+    }
+}
+
+/// The direction a sorting criterion orders values in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Direction {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Where elements that lack the attribute of a sorting criterion go,
+/// whatever the direction. A NaN FRAME-RATE or SCORE counts as missing.
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Nulls {
+    #[default]
+    First,
+    Last,
+}
+
+/// A sorting criterion with its direction and missing-value policy.
+///
+/// Parsed from strings such as `bandwidth`, `bandwidth:desc` or
+/// `resolution:asc:nulls-last`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SortKey<T> {
+    pub by: T,
+    pub direction: Direction,
+    /// The missing-value policy of the key, or `None` to follow
+    /// `SortOptions::nulls`.
+    pub nulls: Option<Nulls>,
+}
+
+impl<T> SortKey<T> {
+    /// Sorts by the criterion in ascending order.
+    pub fn ascending(by: T) -> Self {
+        SortKey {
+            by,
+            direction: Direction::Ascending,
+            nulls: None,
+        }
+    }
+
+    /// Sorts by the criterion in descending order.
+    pub fn descending(by: T) -> Self {
+        SortKey {
+            direction: Direction::Descending,
+            ..Self::ascending(by)
+        }
+    }
+
+    /// Puts elements that lack the attribute first.
+    pub fn nulls_first(self) -> Self {
+        SortKey {
+            nulls: Some(Nulls::First),
+            ..self
+        }
+    }
+
+    /// Puts elements that lack the attribute last.
+    pub fn nulls_last(self) -> Self {
+        SortKey {
+            nulls: Some(Nulls::Last),
+            ..self
+        }
+    }
+
+    /// The key with the missing-value policy of the options if it has none.
+    fn resolve(&self, options: &SortOptions) -> SortKey<T>
+    where
+        T: Copy,
+    {
+        SortKey {
+            nulls: self.nulls.or(Some(options.nulls)),
+            ..*self
+        }
+    }
+
+    /// Orders two values of the criterion, either of which may be missing.
+    fn order<V: Ord>(&self, a: Option<V>, b: Option<V>) -> Ordering {
+        self.order_by(a, b, Ord::cmp)
+    }
+
+    /// Orders two values of the criterion with the given comparison. Missing
+    /// values are placed by the nulls policy and are not reversed by the
+    /// direction.
+    fn order_by<V>(
+        &self,
+        a: Option<V>,
+        b: Option<V>,
+        compare: impl FnOnce(&V, &V) -> Ordering,
+    ) -> Ordering {
+        match (a, b, self.nulls.unwrap_or_default()) {
+            (Some(a), Some(b), _) => match self.direction {
+                Direction::Ascending => compare(&a, &b),
+                Direction::Descending => compare(&a, &b).reverse(),
+            },
+            (None, None, _) => Ordering::Equal,
+            (None, Some(_), Nulls::First) | (Some(_), None, Nulls::Last) => Ordering::Less,
+            (None, Some(_), Nulls::Last) | (Some(_), None, Nulls::First) => Ordering::Greater,
+        }
+    }
+}
+
+impl<T> From<T> for SortKey<T> {
+    fn from(by: T) -> Self {
+        SortKey::ascending(by)
+    }
+}
+
+impl<T: ValueEnum> FromStr for SortKey<T> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let mut key = SortKey::ascending(T::from_str(name, true)?);
+
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "asc" => key.direction = Direction::Ascending,
+                "desc" => key.direction = Direction::Descending,
+                "nulls-first" => key.nulls = Some(Nulls::First),
+                "nulls-last" => key.nulls = Some(Nulls::Last),
+                _ => {
+                    return Err(format!(
+                        "invalid modifier '{}', expected asc, desc, nulls-first or nulls-last",
+                        modifier
+                    ))
+                }
             }
-        });
+        }
+        Ok(key)
+    }
+}
+
+/// Deserializes a sort key from the same syntax as `FromStr`, such as
+/// `bandwidth:desc`.
+impl<'de, T: ValueEnum> Deserialize<'de> for SortKey<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// An ordering of stream variants, media tracks or I-frame streams that
+/// the built-in sort keys cannot express, such as putting one CDN first.
+///
+/// Closures taking two elements implement it, and so do the sort keys, so
+/// the two can be chained with `then`.
+pub trait Comparator<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+
+    /// Breaks the ties of this comparator with another one.
+    fn then<C: Comparator<T>>(self, next: C) -> Then<Self, C>
+    where
+        Self: Sized,
+    {
+        Then(self, next)
     }
 
-    /// Sorts the media tracks within the playlist using primary and
-    /// secondary sorting criteria.
-    pub fn sort_media(&mut self, sort_by: (SortMediaBy, SortMediaBy)) {
-        self.media.sort_by(|a, b| {
-            let primary_cmp = Self::compare_media(a, b, sort_by.0);
-            if primary_cmp == std::cmp::Ordering::Equal {
-                Self::compare_media(a, b, sort_by.1)
-            } else {
-                primary_cmp
+    /// Reverses the order of this comparator.
+    fn reversed(self) -> Reversed<Self>
+    where
+        Self: Sized,
+    {
+        Reversed(self)
+    }
+}
+
+/// A comparator whose ties are broken by another one, made by
+/// `Comparator::then`.
+#[derive(Clone, Copy, Debug)]
+pub struct Then<A, B>(A, B);
+
+/// A comparator in reverse, made by `Comparator::reversed`.
+#[derive(Clone, Copy, Debug)]
+pub struct Reversed<A>(A);
+
+/// A sort key with options other than the defaults, made by
+/// `SortKey::with_options`.
+#[derive(Clone, Copy, Debug)]
+pub struct WithOptions<'a, T> {
+    key: SortKey<T>,
+    options: &'a SortOptions,
+}
+
+/// Orders elements by the key a function extracts.
+pub fn by_key<T, K: Ord>(f: impl Fn(&T) -> K) -> impl Comparator<T> {
+    move |a: &T, b: &T| f(a).cmp(&f(b))
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Comparator<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+impl<T, A: Comparator<T>, B: Comparator<T>> Comparator<T> for Then<A, B> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(a, b).then_with(|| self.1.compare(a, b))
+    }
+}
+
+impl<T, A: Comparator<T>> Comparator<T> for Reversed<A> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(a, b).reverse()
+    }
+}
+
+impl<T> SortKey<T> {
+    /// Compares by the key with the given options instead of the defaults.
+    pub fn with_options(self, options: &SortOptions) -> WithOptions<'_, T> {
+        WithOptions { key: self, options }
+    }
+}
+
+/// Implements `Comparator` for the sort keys of an element, with and
+/// without options, and for the bare criteria as ascending keys.
+macro_rules! impl_key_comparator {
+    ($element:ty, $by:ty, $compare:ident) => {
+        impl Comparator<$element> for WithOptions<'_, $by> {
+            fn compare(&self, a: &$element, b: &$element) -> Ordering {
+                MasterPlaylist::$compare(a, b, &self.key, self.options)
             }
+        }
+
+        impl Comparator<$element> for SortKey<$by> {
+            fn compare(&self, a: &$element, b: &$element) -> Ordering {
+                MasterPlaylist::$compare(a, b, self, &SortOptions::default())
+            }
+        }
+
+        impl Comparator<$element> for $by {
+            fn compare(&self, a: &$element, b: &$element) -> Ordering {
+                SortKey::ascending(*self).compare(a, b)
+            }
+        }
+    };
+}
+
+impl_key_comparator!(StreamVariant, SortStreamBy, compare_stream);
+impl_key_comparator!(MediaTrack, SortMediaBy, compare_media);
+impl_key_comparator!(IFrameStream, SortIFrameBy, compare_iframe);
+
+impl MasterPlaylist {
+    /// Sorts the stream variants within the playlist by the given keys, in
+    /// order of precedence.
+    pub fn sort_stream(&mut self, keys: &[SortKey<SortStreamBy>]) {
+        self.sort_stream_with_options(keys, &SortOptions::default());
+    }
+
+    /// Sorts the stream variants within the playlist by the given keys and
+    /// options.
+    pub fn sort_stream_with_options(
+        &mut self,
+        keys: &[SortKey<SortStreamBy>],
+        options: &SortOptions,
+    ) {
+        self.sort_stream_with(|a: &StreamVariant, b: &StreamVariant| {
+            compare_by_keys(keys, |key| Self::compare_stream(a, b, key, options))
         });
     }
 
-    /// Sorts the I-frame streams within the playlist using primary and
-    /// secondary sorting criteria.
-    pub fn sort_iframe(&mut self, sort_by: (SortIFrameBy, SortIFrameBy)) {
-        self.frames.sort_by(|a, b| {
-            let primary_cmp = Self::compare_iframe(a, b, sort_by.0);
-            if primary_cmp == std::cmp::Ordering::Equal {
-                Self::compare_iframe(a, b, sort_by.1)
-            } else {
-                primary_cmp
+    /// Sorts the stream variants within the playlist with a comparator,
+    /// keeping equal variants in their order.
+    pub fn sort_stream_with(&mut self, comparator: impl Comparator<StreamVariant>) {
+        self.variants.sort_by(|a, b| comparator.compare(a, b));
+    }
+
+    /// Sorts the stream variants within the playlist by the key a function
+    /// extracts, keeping equal variants in their order.
+    pub fn sort_stream_by_key<K: Ord>(&mut self, f: impl FnMut(&StreamVariant) -> K) {
+        self.variants.sort_by_key(f);
+    }
+
+    /// Sorts the media tracks within the playlist by the given keys, in
+    /// order of precedence.
+    pub fn sort_media(&mut self, keys: &[SortKey<SortMediaBy>]) {
+        self.sort_media_with_options(keys, &SortOptions::default());
+    }
+
+    /// Sorts the media tracks within the playlist by the given keys and
+    /// options.
+    pub fn sort_media_with_options(
+        &mut self,
+        keys: &[SortKey<SortMediaBy>],
+        options: &SortOptions,
+    ) {
+        let compare = |a: &MediaTrack, b: &MediaTrack| {
+            compare_by_keys(keys, |key| Self::compare_media(a, b, key, options))
+        };
+        match options.media_groups {
+            Some(group_order) => self.sort_media_groups(group_order, options.pin_default, compare),
+            None => self.sort_media_with(compare),
+        }
+    }
+
+    /// Orders the rendition groups relative to each other, keeping the
+    /// renditions of each group together, and sorts the renditions inside
+    /// each group with a comparator.
+    pub fn sort_media_groups(
+        &mut self,
+        group_order: GroupOrder,
+        pin_default: bool,
+        comparator: impl Comparator<MediaTrack>,
+    ) {
+        let lowest_bandwidths = self.lowest_bandwidths();
+        let first_references = self.first_references();
+
+        // Groups in order of first appearance
+        let mut groups: Vec<(GroupKey, Vec<MediaTrack>)> = Vec::new();
+        for track in self.media.drain(..) {
+            let key = (track.track_type, track.group_id.clone());
+            match groups.iter_mut().find(|(group, _)| *group == key) {
+                Some((_, tracks)) => tracks.push(track),
+                None => groups.push((key, vec![track])),
+            }
+        }
+
+        match group_order {
+            GroupOrder::GroupId => groups.sort_by(|(a, _), (b, _)| a.1.cmp(&b.1)),
+            GroupOrder::Bandwidth => groups.sort_by_key(|(key, _)| {
+                let bandwidth = lowest_bandwidths.get(key).copied();
+                (bandwidth.is_none(), bandwidth)
+            }),
+            GroupOrder::FirstAppearance => {}
+            GroupOrder::Variants => groups.sort_by_key(|(key, _)| {
+                let position = first_references.get(key).copied();
+                (position.is_none(), position)
+            }),
+        }
+
+        for (_, mut tracks) in groups {
+            tracks.sort_by(|a, b| {
+                let pinned = |track: &MediaTrack| pin_default && track.default == Some(true);
+                pinned(b)
+                    .cmp(&pinned(a))
+                    .then_with(|| comparator.compare(a, b))
+            });
+            self.media.extend(tracks);
+        }
+    }
+
+    /// Orders the rendition groups by the position of the first variant
+    /// stream that references them, keeping the renditions of each group
+    /// together and in their order. Meant to follow `sort_stream`, so that a
+    /// player picking the first group gets the one of the first variant.
+    /// `sort_media_with_options` does the same with `GroupOrder::Variants`,
+    /// which is what the CLI's `--group-media-by variants` uses, while also
+    /// sorting the renditions inside each group.
+    pub fn order_media_by_variants(&mut self) {
+        self.sort_media_groups(
+            GroupOrder::Variants,
+            false,
+            |_: &MediaTrack, _: &MediaTrack| Ordering::Equal,
+        );
+    }
+
+    /// The position of the first variant stream that references each
+    /// rendition group, followed by the position of the reference among
+    /// the AUDIO, VIDEO, SUBTITLES and CLOSED-CAPTIONS of the variant.
+    fn first_references(&self) -> HashMap<GroupKey, (usize, usize)> {
+        let mut positions = HashMap::new();
+        for (position, variant) in self.variants.iter().enumerate() {
+            for (index, (media_type, group_id)) in
+                variant.group_references().into_iter().enumerate()
+            {
+                positions
+                    .entry((media_type, group_id.to_string()))
+                    .or_insert((position, index));
+            }
+        }
+        positions
+    }
+
+    /// The lowest BANDWIDTH of the variant streams that reference each
+    /// rendition group.
+    fn lowest_bandwidths(&self) -> HashMap<GroupKey, u64> {
+        let mut bandwidths: HashMap<GroupKey, u64> = HashMap::new();
+        for variant in &self.variants {
+            for (media_type, group_id) in variant.group_references() {
+                let bandwidth = bandwidths
+                    .entry((media_type, group_id.to_string()))
+                    .or_insert(variant.bandwidth);
+                *bandwidth = (*bandwidth).min(variant.bandwidth);
             }
+        }
+        bandwidths
+    }
+
+    /// Sorts the media tracks within the playlist with a comparator, keeping
+    /// equal tracks in their order.
+    pub fn sort_media_with(&mut self, comparator: impl Comparator<MediaTrack>) {
+        self.media.sort_by(|a, b| comparator.compare(a, b));
+    }
+
+    /// Sorts the media tracks within the playlist by the key a function
+    /// extracts, keeping equal tracks in their order.
+    pub fn sort_media_by_key<K: Ord>(&mut self, f: impl FnMut(&MediaTrack) -> K) {
+        self.media.sort_by_key(f);
+    }
+
+    /// Sorts the I-frame streams within the playlist by the given keys, in
+    /// order of precedence.
+    pub fn sort_iframe(&mut self, keys: &[SortKey<SortIFrameBy>]) {
+        self.sort_iframe_with_options(keys, &SortOptions::default());
+    }
+
+    /// Sorts the I-frame streams within the playlist by the given keys and
+    /// options.
+    pub fn sort_iframe_with_options(
+        &mut self,
+        keys: &[SortKey<SortIFrameBy>],
+        options: &SortOptions,
+    ) {
+        self.sort_iframe_with(|a: &IFrameStream, b: &IFrameStream| {
+            compare_by_keys(keys, |key| Self::compare_iframe(a, b, key, options))
         });
     }
 
-    /// Comparison logic for stream variants.
-    fn compare_stream(
+    /// Sorts the I-frame streams within the playlist with a comparator,
+    /// keeping equal streams in their order.
+    pub fn sort_iframe_with(&mut self, comparator: impl Comparator<IFrameStream>) {
+        self.frames.sort_by(|a, b| comparator.compare(a, b));
+    }
+
+    /// Sorts the I-frame streams within the playlist by the key a function
+    /// extracts, keeping equal streams in their order.
+    pub fn sort_iframe_by_key<K: Ord>(&mut self, f: impl FnMut(&IFrameStream) -> K) {
+        self.frames.sort_by_key(f);
+    }
+
+    /// Compares two stream variants by a key. A key without a missing-value
+    /// policy takes the one of the options.
+    pub fn compare_stream(
         a: &StreamVariant,
         b: &StreamVariant,
-        sort_by: SortStreamBy,
-    ) -> std::cmp::Ordering {
-        match sort_by {
-            SortStreamBy::Bandwidth => a.bandwidth.cmp(&b.bandwidth),
-            SortStreamBy::AverageBandwidth => a.average_bandwidth.cmp(&b.average_bandwidth),
-            SortStreamBy::Resolution => a.resolution.cmp(&b.resolution),
-            SortStreamBy::Uri => a.uri.cmp(&b.uri),
-            SortStreamBy::Codecs => a.codecs.cmp(&b.codecs),
-            SortStreamBy::VideoRange => a.video_range.cmp(&b.video_range),
-            SortStreamBy::Audio => a.audio.cmp(&b.audio),
-            SortStreamBy::ClosedCaptions => a.closed_captions.cmp(&b.closed_captions),
-            SortStreamBy::FrameRate => a
-                .frame_rate
-                .partial_cmp(&b.frame_rate)
-                .unwrap_or(std::cmp::Ordering::Equal),
-        }
-    }
-
-    /// Comparison logic for media tracks.
-    fn compare_media(a: &MediaTrack, b: &MediaTrack, sort_by: SortMediaBy) -> std::cmp::Ordering {
-        match sort_by {
-            SortMediaBy::Type => a.track_type.cmp(&b.track_type),
-            SortMediaBy::GroupId => a.group_id.cmp(&b.group_id),
-            SortMediaBy::Name => a.name.cmp(&b.name),
-            SortMediaBy::Language => a.language.cmp(&b.language),
-            SortMediaBy::Default => a.default.cmp(&b.default),
-            SortMediaBy::AutoSelect => a.autoselect.cmp(&b.autoselect),
-            SortMediaBy::Channels => a.channels.cmp(&b.channels),
-            SortMediaBy::Uri => a.uri.cmp(&b.uri),
-        }
-    }
-
-    /// Comparison logic for I-frame streams.
-    fn compare_iframe(
+        key: &SortKey<SortStreamBy>,
+        options: &SortOptions,
+    ) -> Ordering {
+        let key = &key.resolve(options);
+        match key.by {
+            SortStreamBy::Bandwidth => key.order(Some(a.bandwidth), Some(b.bandwidth)),
+            SortStreamBy::AverageBandwidth => key.order(a.average_bandwidth, b.average_bandwidth),
+            SortStreamBy::Resolution => key.order(
+                options.resolution_key(a.resolution),
+                options.resolution_key(b.resolution),
+            ),
+            SortStreamBy::Uri => key.order(Some(&a.uri), Some(&b.uri)),
+            SortStreamBy::Codecs => key.order(a.codecs.as_ref(), b.codecs.as_ref()),
+            SortStreamBy::VideoRange => key.order(a.video_range.as_ref(), b.video_range.as_ref()),
+            SortStreamBy::Audio => key.order(a.audio.as_ref(), b.audio.as_ref()),
+            SortStreamBy::ClosedCaptions => {
+                key.order(a.closed_captions.as_ref(), b.closed_captions.as_ref())
+            }
+            SortStreamBy::FrameRate => {
+                key.order_by(number(a.frame_rate), number(b.frame_rate), f32::total_cmp)
+            }
+            SortStreamBy::ProgramId => key.order(a.program_id, b.program_id),
+            SortStreamBy::Score => key.order_by(number(a.score), number(b.score), f32::total_cmp),
+            SortStreamBy::SupplementalCodecs => key.order(
+                a.supplemental_codecs.as_ref(),
+                b.supplemental_codecs.as_ref(),
+            ),
+            SortStreamBy::HdcpLevel => key.order(a.hdcp_level.as_ref(), b.hdcp_level.as_ref()),
+            SortStreamBy::AllowedCpc => key.order(a.allowed_cpc.as_ref(), b.allowed_cpc.as_ref()),
+            SortStreamBy::ReqVideoLayout => {
+                key.order(a.req_video_layout.as_ref(), b.req_video_layout.as_ref())
+            }
+            SortStreamBy::StableVariantId => {
+                key.order(a.stable_variant_id.as_ref(), b.stable_variant_id.as_ref())
+            }
+            SortStreamBy::Video => key.order(a.video.as_ref(), b.video.as_ref()),
+            SortStreamBy::Subtitles => key.order(a.subtitles.as_ref(), b.subtitles.as_ref()),
+            SortStreamBy::PathwayId => key.order(a.pathway_id.as_ref(), b.pathway_id.as_ref()),
+            SortStreamBy::VideoCodec => key.order(
+                options.codec_rank(a.video_codec()),
+                options.codec_rank(b.video_codec()),
+            ),
+            SortStreamBy::VideoCodecLevel => key.order(
+                level_key(a.video_codec(), options),
+                level_key(b.video_codec(), options),
+            ),
+            SortStreamBy::AudioCodec => key.order(audio_codec_key(a), audio_codec_key(b)),
+            SortStreamBy::DolbyVisionProfile => {
+                key.order(a.dolby_vision_profile(), b.dolby_vision_profile())
+            }
+        }
+    }
+
+    /// Compares two media tracks by a key. A key without a missing-value
+    /// policy takes the one of the options.
+    pub fn compare_media(
+        a: &MediaTrack,
+        b: &MediaTrack,
+        key: &SortKey<SortMediaBy>,
+        options: &SortOptions,
+    ) -> Ordering {
+        let key = &key.resolve(options);
+        match key.by {
+            SortMediaBy::Type => key.order(Some(a.track_type), Some(b.track_type)),
+            SortMediaBy::GroupId => key.order(Some(&a.group_id), Some(&b.group_id)),
+            SortMediaBy::Name => key.order(Some(&a.name), Some(&b.name)),
+            SortMediaBy::Language => key.order(a.language.as_ref(), b.language.as_ref()),
+            SortMediaBy::Default => key.order(a.default, b.default),
+            SortMediaBy::AutoSelect => key.order(a.autoselect, b.autoselect),
+            SortMediaBy::Channels => key.order(a.channels.as_ref(), b.channels.as_ref()),
+            SortMediaBy::Uri => key.order(a.uri.as_ref(), b.uri.as_ref()),
+            SortMediaBy::AssocLanguage => {
+                key.order(a.assoc_language.as_ref(), b.assoc_language.as_ref())
+            }
+            SortMediaBy::StableRenditionId => key.order(
+                a.stable_rendition_id.as_ref(),
+                b.stable_rendition_id.as_ref(),
+            ),
+            SortMediaBy::Forced => key.order(a.forced, b.forced),
+            SortMediaBy::InstreamId => key.order(
+                instream_id_key(&a.instream_id),
+                instream_id_key(&b.instream_id),
+            ),
+            SortMediaBy::BitDepth => key.order(a.bit_depth, b.bit_depth),
+            SortMediaBy::SampleRate => key.order(a.sample_rate, b.sample_rate),
+            SortMediaBy::Characteristics => {
+                key.order(a.characteristics.as_ref(), b.characteristics.as_ref())
+            }
+            SortMediaBy::LanguagePreference => key.order(
+                Some(options.language_rank(a.language.as_ref())),
+                Some(options.language_rank(b.language.as_ref())),
+            ),
+        }
+    }
+
+    /// Compares two I-frame streams by a key. A key without a missing-value
+    /// policy takes the one of the options.
+    pub fn compare_iframe(
         a: &IFrameStream,
         b: &IFrameStream,
-        sort_by: SortIFrameBy,
-    ) -> std::cmp::Ordering {
-        match sort_by {
-            SortIFrameBy::Bandwidth => a.bandwidth.cmp(&b.bandwidth),
-            SortIFrameBy::Resolution => a.resolution.cmp(&b.resolution),
-            SortIFrameBy::Uri => a.uri.cmp(&b.uri),
-            SortIFrameBy::Codecs => a.codecs.cmp(&b.codecs),
-            SortIFrameBy::VideoRange => a.video_range.cmp(&b.video_range),
+        key: &SortKey<SortIFrameBy>,
+        options: &SortOptions,
+    ) -> Ordering {
+        let key = &key.resolve(options);
+        match key.by {
+            SortIFrameBy::Bandwidth => key.order(Some(a.bandwidth), Some(b.bandwidth)),
+            SortIFrameBy::Resolution => key.order(
+                options.resolution_key(a.resolution),
+                options.resolution_key(b.resolution),
+            ),
+            SortIFrameBy::Uri => key.order(Some(&a.uri), Some(&b.uri)),
+            SortIFrameBy::Codecs => key.order(a.codecs.as_ref(), b.codecs.as_ref()),
+            SortIFrameBy::VideoRange => key.order(a.video_range.as_ref(), b.video_range.as_ref()),
+            SortIFrameBy::VideoCodec => key.order(
+                options.codec_rank(a.video_codec()),
+                options.codec_rank(b.video_codec()),
+            ),
+            SortIFrameBy::VideoCodecLevel => key.order(
+                level_key(a.video_codec(), options),
+                level_key(b.video_codec(), options),
+            ),
+            SortIFrameBy::DolbyVisionProfile => {
+                key.order(a.dolby_vision_profile(), b.dolby_vision_profile())
+            }
         }
     }
 }
 
-/// Helper function to get the primary and secondary sorting order.
-///
-/// # Parameters
-/// - `sort_order`: A slice of sorting criteria.
-///
-/// # Returns
-/// A tuple with the primary and secondary sorting criteria. Defaults are
-/// used if not provided.
-pub fn get_sort_order<T: Clone + Default>(sort_order: &[T]) -> (T, T) {
-    let primary = sort_order.first().cloned().unwrap_or_default();
-    let secondary = sort_order.get(1).cloned().unwrap_or_default();
-    (primary, secondary)
+/// A rendition group, by TYPE and GROUP-ID.
+type GroupKey = (MediaType, String);
+
+/// Compares by each key in turn, falling through to the next key while
+/// equal.
+fn compare_by_keys<T>(keys: &[SortKey<T>], compare: impl Fn(&SortKey<T>) -> Ordering) -> Ordering {
+    keys.iter()
+        .map(compare)
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Whether a BCP 47 language range matches a language tag, ignoring case:
+/// the range is the tag, or a prefix of it ending at a subtag, so that `es`
+/// matches `es-MX`.
+pub(crate) fn language_matches(range: &str, tag: &str) -> bool {
+    match tag.get(..range.len()) {
+        Some(prefix) => {
+            prefix.eq_ignore_ascii_case(range)
+                && matches!(tag.as_bytes().get(range.len()), None | Some(b'-'))
+        }
+        None => false,
+    }
+}
+
+/// A floating point attribute, with NaN counted as missing so that the
+/// comparison is a total order.
+fn number(value: Option<f32>) -> Option<f32> {
+    value.filter(|value| !value.is_nan())
+}
+
+/// Splits an INSTREAM-ID such as `CC2` or `SERVICE12` into its prefix and
+/// channel number, so that `SERVICE2` sorts before `SERVICE12`.
+fn instream_id_key(instream_id: &Option<String>) -> Option<(&str, u32)> {
+    instream_id.as_deref().map(|id| {
+        let split = id.find(|c: char| c.is_ascii_digit()).unwrap_or(id.len());
+        let (prefix, number) = id.split_at(split);
+        (prefix, number.parse().unwrap_or(0))
+    })
+}
+
+/// The family rank, the level and then the tier of a video codec. Levels
+/// are only compared within a family, as the families number them on
+/// different scales, and a high tier stream sorts after a main tier stream
+/// of the same level.
+fn level_key(codec: Option<Codec>, options: &SortOptions) -> Option<(usize, u32, Option<Tier>)> {
+    let codec = codec?;
+    let level = codec.level()?;
+    let tier = codec.tier();
+    Some((options.codec_rank(Some(codec))?, level, tier))
+}
+
+/// The family and then the codec string of the first audio codec of a
+/// stream variant.
+fn audio_codec_key(variant: &StreamVariant) -> Option<(Option<AudioCodecFamily>, String)> {
+    variant
+        .audio_codecs()
+        .into_iter()
+        .next()
+        .map(|codec| (codec.audio_family(), codec.id))
 }
 
 #[cfg(test)]
@@ -191,6 +836,495 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sort_stream_by_hdcp_level_then_score() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,SCORE=2.0,HDCP-LEVEL=TYPE-1
+uhd.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,SCORE=1.0,HDCP-LEVEL=NONE
+sd.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,SCORE=3.0,HDCP-LEVEL=TYPE-0
+hd-high.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,SCORE=1.5,HDCP-LEVEL=TYPE-0
+hd-low.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        playlist.sort_stream(&[SortStreamBy::HdcpLevel.into(), SortStreamBy::Score.into()]);
+
+        let uris: Vec<&str> = playlist.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(uris, ["sd.m3u8", "hd-low.m3u8", "hd-high.m3u8", "uhd.m3u8"]);
+    }
+
+    #[test]
+    fn test_sort_media_by_instream_id() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"Service 12\",INSTREAM-ID=\"SERVICE12\"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"Service 2\",INSTREAM-ID=\"SERVICE2\"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"CC3\",INSTREAM-ID=\"CC3\"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"CC1\",INSTREAM-ID=\"CC1\"
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        playlist.sort_media(&[SortMediaBy::InstreamId.into(), SortMediaBy::Name.into()]);
+
+        let ids: Vec<&str> = playlist
+            .media
+            .iter()
+            .filter_map(|m| m.instream_id.as_deref())
+            .collect();
+        assert_eq!(ids, ["CC1", "CC3", "SERVICE2", "SERVICE12"]);
+    }
+
+    #[test]
+    fn test_sort_stream_by_video_codec_then_level() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1,CODECS=\"av01.0.08M.08,mp4a.40.2\"
+av1.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2,CODECS=\"hvc1.2.4.H120.90,ec-3\"
+hevc-high.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3,CODECS=\"mp4a.40.2\"
+audio.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4,CODECS=\"avc1.640028,mp4a.40.2\"
+avc-40.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5,CODECS=\"hvc1.2.4.L93.90,mp4a.40.2\"
+hevc-31.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=6,CODECS=\"avc1.64001f,mp4a.40.2\"
+avc-31.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=7,CODECS=\"hvc1.2.4.L120.90,mp4a.40.2\"
+hevc-main.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let uris = |playlist: &MasterPlaylist| -> Vec<String> {
+            playlist.variants.iter().map(|v| v.uri.clone()).collect()
+        };
+
+        playlist.sort_stream(&[
+            SortKey::ascending(SortStreamBy::VideoCodec).nulls_last(),
+            SortKey::ascending(SortStreamBy::VideoCodecLevel),
+        ]);
+        assert_eq!(
+            uris(&playlist),
+            [
+                "avc-31.m3u8",
+                "avc-40.m3u8",
+                "hevc-31.m3u8",
+                "hevc-main.m3u8",
+                "hevc-high.m3u8",
+                "av1.m3u8",
+                "audio.m3u8"
+            ]
+        );
+
+        let options = SortOptions {
+            codec_order: vec![VideoCodecFamily::Av1, VideoCodecFamily::Hevc],
+            ..Default::default()
+        };
+        playlist.sort_stream_with_options(
+            &[
+                SortKey::ascending(SortStreamBy::VideoCodec).nulls_last(),
+                SortKey::ascending(SortStreamBy::AudioCodec),
+            ],
+            &options,
+        );
+        assert_eq!(
+            uris(&playlist),
+            [
+                "av1.m3u8",
+                "hevc-31.m3u8",
+                "hevc-main.m3u8",
+                "hevc-high.m3u8",
+                "avc-31.m3u8",
+                "avc-40.m3u8",
+                "audio.m3u8"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_stream_by_level_within_family() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1,CODECS=\"dvh1.05.09\"
+dv-9.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2,CODECS=\"hvc1.2.4.L150.90\"
+hevc-50.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3,CODECS=\"dvh1.05.01\"
+dv-1.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4,CODECS=\"hvc1.2.4.L93.90\"
+hevc-31.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        playlist.sort_stream(&[SortStreamBy::VideoCodecLevel.into()]);
+
+        let uris: Vec<_> = playlist.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            ["hevc-31.m3u8", "hevc-50.m3u8", "dv-1.m3u8", "dv-9.m3u8"]
+        );
+    }
+
+    #[test]
+    fn test_sort_stream_by_dolby_vision_profile() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1,CODECS=\"dvh1.08.06\"
+dv8.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2,CODECS=\"hvc1.2.4.L150.90\",SUPPLEMENTAL-CODECS=\"dvh1.05.06/db4h\"
+dv5.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3,CODECS=\"hvc1.2.4.L150.90\"
+hdr10.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        playlist.sort_stream(&[
+            SortStreamBy::DolbyVisionProfile.into(),
+            SortStreamBy::Bandwidth.into(),
+        ]);
+
+        let uris: Vec<&str> = playlist.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(uris, ["hdr10.m3u8", "dv5.m3u8", "dv8.m3u8"]);
+    }
+
+    #[test]
+    fn test_sort_stream_by_keys_with_direction_and_nulls() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=640x360,FRAME-RATE=30
+low-30.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000
+audio.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=60
+high-60.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1280x720,FRAME-RATE=30
+high-30.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=640x360,FRAME-RATE=30
+low-30-b.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let keys: Vec<SortKey<SortStreamBy>> =
+            ["resolution:desc:nulls-last", "frame-rate", "bandwidth:desc"]
+                .iter()
+                .map(|key| key.parse().unwrap())
+                .collect();
+        playlist.sort_stream(&keys);
+
+        let uris: Vec<&str> = playlist.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            [
+                "high-30.m3u8",
+                "high-60.m3u8",
+                "low-30-b.m3u8",
+                "low-30.m3u8",
+                "audio.m3u8"
+            ]
+        );
+
+        assert_eq!(
+            "Bandwidth:ASC".parse::<SortKey<SortStreamBy>>(),
+            Ok(SortKey::ascending(SortStreamBy::Bandwidth))
+        );
+        assert!("bandwidth:up".parse::<SortKey<SortStreamBy>>().is_err());
+        assert!("bogus".parse::<SortKey<SortStreamBy>>().is_err());
+    }
+
+    #[test]
+    fn test_sort_stream_nulls_policy_and_nan() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"
+audio.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1280x720,FRAME-RATE=NaN
+nan.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3000000,RESOLUTION=1280x720,FRAME-RATE=60
+fast.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=640x360,FRAME-RATE=30
+slow.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let uris = |playlist: &MasterPlaylist| -> Vec<String> {
+            playlist.variants.iter().map(|v| v.uri.clone()).collect()
+        };
+
+        // Missing values follow the options, and a NaN FRAME-RATE is missing
+        let options = SortOptions {
+            nulls: Nulls::Last,
+            ..Default::default()
+        };
+        playlist
+            .sort_stream_with_options(&[SortKey::descending(SortStreamBy::FrameRate)], &options);
+        assert_eq!(
+            uris(&playlist),
+            ["fast.m3u8", "slow.m3u8", "audio.m3u8", "nan.m3u8"]
+        );
+
+        // A key's own policy takes precedence over the options
+        playlist.sort_stream_with_options(
+            &[
+                SortKey::ascending(SortStreamBy::Resolution).nulls_first(),
+                SortKey::ascending(SortStreamBy::Bandwidth),
+            ],
+            &options,
+        );
+        assert_eq!(
+            uris(&playlist),
+            ["audio.m3u8", "slow.m3u8", "nan.m3u8", "fast.m3u8"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_resolution_order() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1,RESOLUTION=1920x800
+scope.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2,RESOLUTION=1440x1080
+anamorphic.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=3,RESOLUTION=1280x720
+hd.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=1,RESOLUTION=1920x800,URI=\"scope-iframe.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=2,RESOLUTION=1440x1080,URI=\"anamorphic-iframe.m3u8\"
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let mut sorted = |resolution_order| -> (Vec<String>, Vec<String>) {
+            let options = SortOptions {
+                resolution_order,
+                ..Default::default()
+            };
+            playlist.sort_stream_with_options(&[SortStreamBy::Resolution.into()], &options);
+            playlist.sort_iframe_with_options(&[SortIFrameBy::Resolution.into()], &options);
+            (
+                playlist.variants.iter().map(|v| v.uri.clone()).collect(),
+                playlist.frames.iter().map(|f| f.uri.clone()).collect(),
+            )
+        };
+
+        let (variants, frames) = sorted(ResolutionOrder::WidthFirst);
+        assert_eq!(variants, ["hd.m3u8", "anamorphic.m3u8", "scope.m3u8"]);
+        assert_eq!(frames, ["anamorphic-iframe.m3u8", "scope-iframe.m3u8"]);
+
+        let (variants, _) = sorted(ResolutionOrder::HeightFirst);
+        assert_eq!(variants, ["hd.m3u8", "scope.m3u8", "anamorphic.m3u8"]);
+
+        // 1440x1080 has 1555200 pixels and 1920x800 has 1536000
+        let (variants, frames) = sorted(ResolutionOrder::Area);
+        assert_eq!(variants, ["hd.m3u8", "scope.m3u8", "anamorphic.m3u8"]);
+        assert_eq!(frames, ["scope-iframe.m3u8", "anamorphic-iframe.m3u8"]);
+    }
+
+    #[test]
+    fn test_sort_with_comparators() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"aac\"
+https://cdn-b.example.com/low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aac\"
+https://cdn-a.example.com/high.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"eac3\"
+https://cdn-a.example.com/low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"eac3\"
+https://cdn-b.example.com/high.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let uris = |playlist: &MasterPlaylist| -> Vec<String> {
+            playlist.variants.iter().map(|v| v.uri.clone()).collect()
+        };
+
+        // CDN-A first, then by descending bandwidth
+        playlist.sort_stream_with(
+            by_key(|v: &StreamVariant| !v.uri.starts_with("https://cdn-a."))
+                .then(SortKey::descending(SortStreamBy::Bandwidth)),
+        );
+        assert_eq!(
+            uris(&playlist),
+            [
+                "https://cdn-a.example.com/high.m3u8",
+                "https://cdn-a.example.com/low.m3u8",
+                "https://cdn-b.example.com/high.m3u8",
+                "https://cdn-b.example.com/low.m3u8"
+            ]
+        );
+
+        // eac3 before aac, then by ascending bandwidth
+        let eac3_first = |a: &StreamVariant, b: &StreamVariant| {
+            let is_eac3 = |v: &StreamVariant| v.audio.as_deref() == Some("eac3");
+            is_eac3(a).cmp(&is_eac3(b))
+        };
+        playlist.sort_stream_with(eac3_first.reversed().then(SortStreamBy::Bandwidth));
+        assert_eq!(
+            uris(&playlist),
+            [
+                "https://cdn-a.example.com/low.m3u8",
+                "https://cdn-b.example.com/high.m3u8",
+                "https://cdn-b.example.com/low.m3u8",
+                "https://cdn-a.example.com/high.m3u8"
+            ]
+        );
+
+        playlist.sort_stream_by_key(|v| v.uri.clone());
+        assert_eq!(uris(&playlist)[0], "https://cdn-a.example.com/high.m3u8");
+
+        // The built-in comparisons can be used directly
+        let (a, b) = (&playlist.variants[0], &playlist.variants[1]);
+        let key = SortKey::ascending(SortStreamBy::Bandwidth);
+        assert_eq!(
+            MasterPlaylist::compare_stream(a, b, &key, &SortOptions::default()),
+            Ordering::Greater
+        );
+        assert_eq!(
+            key.with_options(&SortOptions::default()).compare(a, b),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_sort_media_by_language_preference() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Deutsch\",LANGUAGE=\"de\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Espanol (Mexico)\",LANGUAGE=\"es-MX\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Commentary\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English (UK)\",LANGUAGE=\"en-GB\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Espanol (Latinoamerica)\",LANGUAGE=\"es-419\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English (US)\",LANGUAGE=\"en-us\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Japanese\",LANGUAGE=\"ja\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Francais\",LANGUAGE=\"fr\"
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let options = SortOptions {
+            language_preferences: ["en-US", "en", "es-419", "es", "fr"]
+                .map(String::from)
+                .to_vec(),
+            ..Default::default()
+        };
+        playlist.sort_media_with_options(&[SortMediaBy::LanguagePreference.into()], &options);
+
+        let names: Vec<&str> = playlist.media.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "English (US)",
+                "English (UK)",
+                "Espanol (Latinoamerica)",
+                "Espanol (Mexico)",
+                "Francais",
+                "Deutsch",
+                "Commentary",
+                "Japanese"
+            ]
+        );
+        assert!(!language_matches("es", "est"));
+    }
+
+    #[test]
+    fn test_sort_media_by_group() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"stereo\",NAME=\"French\",LANGUAGE=\"fr\"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"English\",LANGUAGE=\"en\",URI=\"subs-en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"surround\",NAME=\"English\",LANGUAGE=\"en\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"stereo\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"surround\",NAME=\"Deutsch\",LANGUAGE=\"de\",DEFAULT=YES
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"stereo\",NAME=\"Deutsch\",LANGUAGE=\"de\"
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,AUDIO=\"stereo\"
+high.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"surround\"
+low.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let mut sorted = |media_groups, pin_default| -> Vec<String> {
+            let options = SortOptions {
+                media_groups: Some(media_groups),
+                pin_default,
+                ..Default::default()
+            };
+            playlist.sort_media_with_options(&[SortMediaBy::Name.into()], &options);
+            playlist
+                .media
+                .iter()
+                .map(|m| format!("{}/{}", m.group_id, m.name))
+                .collect()
+        };
+
+        assert_eq!(
+            sorted(GroupOrder::GroupId, false),
+            [
+                "stereo/Deutsch",
+                "stereo/English",
+                "stereo/French",
+                "subs/English",
+                "surround/Deutsch",
+                "surround/English"
+            ]
+        );
+        assert_eq!(
+            sorted(GroupOrder::Bandwidth, true),
+            [
+                "surround/Deutsch",
+                "surround/English",
+                "stereo/English",
+                "stereo/Deutsch",
+                "stereo/French",
+                "subs/English"
+            ]
+        );
+        assert_eq!(
+            sorted(GroupOrder::FirstAppearance, false),
+            [
+                "surround/Deutsch",
+                "surround/English",
+                "stereo/Deutsch",
+                "stereo/English",
+                "stereo/French",
+                "subs/English"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_media_by_variants() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",LANGUAGE=\"en\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"unused\",NAME=\"English\",LANGUAGE=\"en\"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"English\",LANGUAGE=\"en\",URI=\"subs.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"eac3\",NAME=\"English\",LANGUAGE=\"en\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"French\",LANGUAGE=\"fr\"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AUDIO=\"aac\",SUBTITLES=\"subs\"
+aac.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"eac3\",SUBTITLES=\"subs\"
+eac3.m3u8
+";
+        let mut playlist = parse_playlist(input).expect("Failed to parse playlist");
+        playlist.sort_stream(&[SortKey::descending(SortStreamBy::Bandwidth)]);
+        let renditions = |playlist: &MasterPlaylist| -> Vec<String> {
+            playlist
+                .media
+                .iter()
+                .map(|m| format!("{}/{}", m.group_id, m.name))
+                .collect()
+        };
+
+        // The sort path of --group-media-by variants
+        let mut sorted = playlist.clone();
+        let options = SortOptions {
+            media_groups: Some(GroupOrder::Variants),
+            ..Default::default()
+        };
+        sorted.sort_media_with_options(&[SortKey::descending(SortMediaBy::Name)], &options);
+
+        playlist.order_media_by_variants();
+        assert_eq!(
+            renditions(&playlist),
+            [
+                "eac3/English",
+                "subs/English",
+                "aac/English",
+                "aac/French",
+                "unused/English"
+            ]
+        );
+        assert_eq!(
+            renditions(&sorted),
+            [
+                "eac3/English",
+                "subs/English",
+                "aac/French",
+                "aac/English",
+                "unused/English"
+            ]
+        );
+    }
+
     fn test_sort_playlist(
         input_file: &str,
         expected_file: &str,
@@ -216,9 +1350,9 @@ mod tests {
         let mut playlist = result.unwrap();
 
         // Step 3: Apply sorting for streams, media, and iframes
-        playlist.sort_stream(stream_sort_by);
-        playlist.sort_media(media_sort_by);
-        playlist.sort_iframe(iframe_sort_by);
+        playlist.sort_stream(&[stream_sort_by.0.into(), stream_sort_by.1.into()]);
+        playlist.sort_media(&[media_sort_by.0.into(), media_sort_by.1.into()]);
+        playlist.sort_iframe(&[iframe_sort_by.0.into(), iframe_sort_by.1.into()]);
 
         // Step 4: Serialize the sorted playlist back to a string using write_to
         let mut serialized_output = Vec::new();
@@ -0,0 +1,241 @@
+//! This module provides content steering operations for M3U8 master playlists. Each variant
+//! stream and I-frame stream belongs to the pathway named by its PATHWAY-ID attribute, so a
+//! single playlist can describe the same ladder on several CDNs. The playlist can be narrowed
+//! down to one pathway, or a pathway can be copied to a new CDN host.
+
+use crate::parser::{ClosedCaptions, MasterPlaylist, MediaType, StreamVariant};
+use reqwest::Url;
+use std::collections::HashSet;
+
+/// The pathway of variant streams that have no PATHWAY-ID attribute.
+pub const DEFAULT_PATHWAY_ID: &str = ".";
+
+impl MasterPlaylist {
+    /// Lists the distinct pathways of the variant streams and I-frame
+    /// streams, in order of first appearance.
+    pub fn pathways(&self) -> Vec<&str> {
+        let mut pathways = Vec::new();
+        let ids = self
+            .variants
+            .iter()
+            .map(|variant| &variant.pathway_id)
+            .chain(self.frames.iter().map(|frame| &frame.pathway_id));
+
+        for id in ids {
+            let id = pathway_or_default(id);
+            if !pathways.contains(&id) {
+                pathways.push(id);
+            }
+        }
+        pathways
+    }
+
+    /// Removes the variant streams and I-frame streams of every other
+    /// pathway. Renditions of groups that were only referenced by the removed
+    /// variant streams are removed as well. The content steering tag, if any,
+    /// names the kept pathway as the initial one, or none for the default
+    /// pathway.
+    pub fn retain_pathway(&mut self, pathway_id: &str) {
+        self.retain_variants(|variant| pathway_or_default(&variant.pathway_id) == pathway_id);
+        self.frames
+            .retain(|frame| pathway_or_default(&frame.pathway_id) == pathway_id);
+        if let Some(ref mut steering) = self.content_steering {
+            steering.pathway_id =
+                Some(pathway_id.to_string()).filter(|id| id != DEFAULT_PATHWAY_ID);
+        }
+    }
+
+    /// Removes the variant streams a predicate rejects. Renditions of groups
+    /// that were only referenced by the removed variant streams are removed
+    /// as well.
+    pub fn retain_variants(&mut self, f: impl FnMut(&StreamVariant) -> bool) {
+        let referenced_before = self.referenced_groups();
+
+        self.variants.retain(f);
+
+        let referenced_after = self.referenced_groups();
+        self.media.retain(|track| {
+            let group = (track.track_type, track.group_id.clone());
+            !referenced_before.contains(&group) || referenced_after.contains(&group)
+        });
+    }
+
+    /// Adds a copy of the pathway `base_id` as the new pathway `new_id`, with
+    /// the host of every absolute URI replaced by `host`. The rendition
+    /// groups referenced by the base pathway are copied too, under a GROUP-ID
+    /// suffixed with the new pathway ID, so that the renditions are fetched
+    /// from the new host as well. Relative URIs are kept as they are.
+    pub fn clone_pathway(&mut self, base_id: &str, new_id: &str, host: &str) {
+        let clone_group = |group_id: &str| format!("{}-{}", group_id, new_id);
+
+        let base_variants: Vec<_> = self
+            .variants
+            .iter()
+            .filter(|variant| pathway_or_default(&variant.pathway_id) == base_id)
+            .cloned()
+            .collect();
+        let base_groups: HashSet<(MediaType, String)> = base_variants
+            .iter()
+            .flat_map(|variant| variant.group_references())
+            .map(|(media_type, group_id)| (media_type, group_id.to_string()))
+            .collect();
+
+        let cloned_media: Vec<_> = self
+            .media
+            .iter()
+            .filter(|track| base_groups.contains(&(track.track_type, track.group_id.clone())))
+            .map(|track| {
+                let mut track = track.clone();
+                track.group_id = clone_group(&track.group_id);
+                track.uri = track.uri.as_deref().map(|uri| replace_host(uri, host));
+                // The copies are new elements, written after the originals
+                track.source.index = None;
+                track
+            })
+            .collect();
+
+        let cloned_variants: Vec<_> = base_variants
+            .into_iter()
+            .map(|mut variant| {
+                variant.pathway_id = Some(new_id.to_string());
+                variant.uri = replace_host(&variant.uri, host);
+                variant.source.index = None;
+                variant.audio = variant.audio.as_deref().map(clone_group);
+                variant.video = variant.video.as_deref().map(clone_group);
+                variant.subtitles = variant.subtitles.as_deref().map(clone_group);
+                if let Some(ClosedCaptions::GroupId(ref mut group_id)) = variant.closed_captions {
+                    *group_id = clone_group(group_id);
+                }
+                variant
+            })
+            .collect();
+
+        let cloned_frames: Vec<_> = self
+            .frames
+            .iter()
+            .filter(|frame| pathway_or_default(&frame.pathway_id) == base_id)
+            .map(|frame| {
+                let mut frame = frame.clone();
+                frame.pathway_id = Some(new_id.to_string());
+                frame.uri = replace_host(&frame.uri, host);
+                frame.source.index = None;
+                frame
+            })
+            .collect();
+
+        self.media.extend(cloned_media);
+        self.variants.extend(cloned_variants);
+        self.frames.extend(cloned_frames);
+    }
+
+    /// The rendition groups referenced by any of the variant streams
+    fn referenced_groups(&self) -> HashSet<(MediaType, String)> {
+        self.variants
+            .iter()
+            .flat_map(|variant| variant.group_references())
+            .map(|(media_type, group_id)| (media_type, group_id.to_string()))
+            .collect()
+    }
+}
+
+fn pathway_or_default(pathway_id: &Option<String>) -> &str {
+    pathway_id.as_deref().unwrap_or(DEFAULT_PATHWAY_ID)
+}
+
+/// Replaces the host of an absolute URI, leaving relative URIs unchanged
+fn replace_host(uri: &str, host: &str) -> String {
+    match Url::parse(uri) {
+        Ok(mut url) if url.has_host() => match url.set_host(Some(host)) {
+            Ok(()) => url.to_string(),
+            Err(_) => uri.to_string(),
+        },
+        _ => uri.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_playlist;
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXT-X-CONTENT-STEERING:SERVER-URI=\"https://steering.example.com/manifest.json\",PATHWAY-ID=\"CDN-A\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac-a\",NAME=\"English\",URI=\"https://cdn-a.example.com/audio.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac-b\",NAME=\"English\",URI=\"https://cdn-b.example.com/audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac-a\",PATHWAY-ID=\"CDN-A\"
+https://cdn-a.example.com/low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac-b\",PATHWAY-ID=\"CDN-B\"
+https://cdn-b.example.com/low.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=80000,PATHWAY-ID=\"CDN-A\",URI=\"https://cdn-a.example.com/low-iframe.m3u8\"
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=80000,PATHWAY-ID=\"CDN-B\",URI=\"https://cdn-b.example.com/low-iframe.m3u8\"
+";
+
+    #[test]
+    fn test_pathways() {
+        let playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        assert_eq!(playlist.pathways(), ["CDN-A", "CDN-B"]);
+        assert_eq!(
+            playlist.content_steering.unwrap().pathway_id.as_deref(),
+            Some("CDN-A")
+        );
+    }
+
+    #[test]
+    fn test_retain_pathway() {
+        let mut playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        playlist.retain_pathway("CDN-B");
+
+        assert_eq!(playlist.pathways(), ["CDN-B"]);
+        assert_eq!(playlist.variants.len(), 1);
+        assert_eq!(playlist.frames.len(), 1);
+        assert_eq!(playlist.media.len(), 1);
+        assert_eq!(playlist.media[0].group_id, "aac-b");
+        assert_eq!(
+            playlist
+                .content_steering
+                .as_ref()
+                .unwrap()
+                .pathway_id
+                .as_deref(),
+            Some("CDN-B")
+        );
+
+        playlist.retain_pathway(DEFAULT_PATHWAY_ID);
+        assert!(playlist.variants.is_empty());
+        assert_eq!(playlist.content_steering.unwrap().pathway_id, None);
+    }
+
+    #[test]
+    fn test_clone_pathway() {
+        let mut playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        playlist.clone_pathway("CDN-A", "CDN-C", "cdn-c.example.com");
+
+        assert_eq!(playlist.pathways(), ["CDN-A", "CDN-B", "CDN-C"]);
+
+        let variant = playlist.variants.last().unwrap();
+        assert_eq!(variant.uri, "https://cdn-c.example.com/low.m3u8");
+        assert_eq!(variant.audio.as_deref(), Some("aac-a-CDN-C"));
+
+        let track = playlist.media.last().unwrap();
+        assert_eq!(track.group_id, "aac-a-CDN-C");
+        assert_eq!(
+            track.uri.as_deref(),
+            Some("https://cdn-c.example.com/audio.m3u8")
+        );
+
+        let frame = playlist.frames.last().unwrap();
+        assert_eq!(frame.uri, "https://cdn-c.example.com/low-iframe.m3u8");
+    }
+
+    #[test]
+    fn test_replace_host_keeps_relative_uris() {
+        assert_eq!(
+            replace_host("low/vod.m3u8", "cdn.example.com"),
+            "low/vod.m3u8"
+        );
+        assert_eq!(
+            replace_host("https://a.example.com:8443/x.m3u8?t=1", "b.example.com"),
+            "https://b.example.com:8443/x.m3u8?t=1"
+        );
+    }
+}
@@ -0,0 +1,776 @@
+//! This module checks M3U8 master playlists against the rules of RFC 8216 that a parsed
+//! playlist can still break, such as a variant stream referring to a rendition group that is
+//! not defined. Each rule has an ID and a severity, and every violation is reported as a
+//! `Finding` that points at the offending tag.
+//!
+//! Rules are grouped into profiles: the RFC 8216 profile, and the Apple profile, which adds
+//! the requirements of Apple's HLS Authoring Specification. A `ValidationConfig` loaded from
+//! TOML selects the profile and turns individual rules on or off.
+
+use crate::codecs::CodecType;
+use crate::errors::PlaylistError;
+use crate::parser::{
+    ClosedCaptions, MasterPlaylist, MediaTrack, MediaType, StreamVariant, VideoRange,
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{fmt, fs, path::Path};
+
+/// How serious a finding is. Errors break a MUST of the specification,
+/// warnings a SHOULD.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// The tag a finding was made at, by its index in the `variants`, `media`
+/// or `frames` of the playlist
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Location {
+    Playlist,
+    Variant(usize),
+    Media(usize),
+    IFrame(usize),
+}
+
+/// A violation of a rule
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+/// A rule a master playlist is checked against
+#[derive(Clone, Copy, Debug)]
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+    check: fn(&MasterPlaylist) -> Vec<(Location, String)>,
+}
+
+/// The rules of RFC 8216 for master playlists
+pub const RFC8216_RULES: &[Rule] = &[
+    Rule {
+        id: "undefined-group",
+        severity: Severity::Error,
+        description:
+            "Every rendition group referenced by a variant stream is defined by EXT-X-MEDIA tags",
+        check: check_undefined_groups,
+    },
+    Rule {
+        id: "multiple-defaults",
+        severity: Severity::Error,
+        description: "At most one rendition in a group has DEFAULT=YES",
+        check: check_multiple_defaults,
+    },
+    Rule {
+        id: "default-not-autoselect",
+        severity: Severity::Error,
+        description: "AUTOSELECT is YES, if present, when DEFAULT is YES",
+        check: check_default_not_autoselect,
+    },
+    Rule {
+        id: "duplicate-name",
+        severity: Severity::Error,
+        description: "Renditions in the same group have different NAMEs",
+        check: check_duplicate_names,
+    },
+    Rule {
+        id: "missing-instream-id",
+        severity: Severity::Error,
+        description: "CLOSED-CAPTIONS renditions have an INSTREAM-ID",
+        check: check_missing_instream_id,
+    },
+    Rule {
+        id: "unexpected-instream-id",
+        severity: Severity::Error,
+        description: "Only CLOSED-CAPTIONS renditions have an INSTREAM-ID",
+        check: check_unexpected_instream_id,
+    },
+    Rule {
+        id: "closed-captions-uri",
+        severity: Severity::Error,
+        description: "CLOSED-CAPTIONS renditions have no URI",
+        check: check_closed_captions_uri,
+    },
+    Rule {
+        id: "unexpected-forced",
+        severity: Severity::Error,
+        description: "Only SUBTITLES renditions have a FORCED attribute",
+        check: check_unexpected_forced,
+    },
+    Rule {
+        id: "average-bandwidth-exceeds-bandwidth",
+        severity: Severity::Error,
+        description: "AVERAGE-BANDWIDTH is at most the peak BANDWIDTH",
+        check: check_average_bandwidth,
+    },
+    Rule {
+        id: "mixed-closed-captions-none",
+        severity: Severity::Error,
+        description: "If any variant stream has CLOSED-CAPTIONS=NONE, all of them do",
+        check: check_mixed_closed_captions_none,
+    },
+    Rule {
+        id: "missing-codecs",
+        severity: Severity::Warning,
+        description: "Every variant stream has a CODECS attribute",
+        check: check_missing_codecs,
+    },
+];
+
+/// The rules of Apple's HLS Authoring Specification that go beyond RFC 8216
+pub const APPLE_RULES: &[Rule] = &[
+    Rule {
+        id: "video-codecs",
+        severity: Severity::Error,
+        description: "Every video variant stream has a CODECS attribute",
+        check: check_video_codecs,
+    },
+    Rule {
+        id: "video-resolution",
+        severity: Severity::Error,
+        description: "Every video variant stream has a RESOLUTION attribute",
+        check: check_video_resolution,
+    },
+    Rule {
+        id: "video-frame-rate",
+        severity: Severity::Error,
+        description: "Every video variant stream has a FRAME-RATE attribute",
+        check: check_video_frame_rate,
+    },
+    Rule {
+        id: "missing-iframe-stream",
+        severity: Severity::Error,
+        description:
+            "Every video variant stream has an I-frame stream of the same RESOLUTION and VIDEO-RANGE",
+        check: check_missing_iframe_streams,
+    },
+    Rule {
+        id: "hdr-without-sdr",
+        severity: Severity::Error,
+        description: "Every HDR variant stream has an SDR variant stream of the same RESOLUTION",
+        check: check_hdr_without_sdr,
+    },
+    Rule {
+        id: "ladder-step-ratio",
+        severity: Severity::Warning,
+        description: "Adjacent BANDWIDTHs of a bitrate ladder are a factor of 1.5 to 2 apart",
+        check: check_ladder_step_ratio,
+    },
+    Rule {
+        id: "missing-independent-segments",
+        severity: Severity::Error,
+        description: "The playlist has an EXT-X-INDEPENDENT-SEGMENTS tag",
+        check: check_independent_segments,
+    },
+];
+
+/// The smallest and largest ratio between adjacent BANDWIDTHs of a bitrate
+/// ladder that the Apple profile allows
+const LADDER_STEP_RATIO: (f64, f64) = (1.5, 2.0);
+
+/// A set of rules to check playlists against
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// The rules of RFC 8216
+    #[default]
+    Rfc8216,
+    /// The rules of RFC 8216 and of Apple's HLS Authoring Specification
+    Apple,
+}
+
+impl Profile {
+    /// The rules of the profile, in order
+    pub fn rules(self) -> Vec<Rule> {
+        match self {
+            Profile::Rfc8216 => RFC8216_RULES.to_vec(),
+            Profile::Apple => RFC8216_RULES.iter().chain(APPLE_RULES).copied().collect(),
+        }
+    }
+}
+
+/// The profile to validate with and the rules turned on or off on top of
+/// it, as loaded from a TOML file such as:
+///
+/// ```toml
+/// profile = "apple"
+///
+/// [rules]
+/// ladder-step-ratio = false
+/// ```
+#[derive(Clone, Default, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationConfig {
+    #[serde(default)]
+    pub profile: Profile,
+    /// Whether each rule is on, by rule ID. Rules not listed are on when
+    /// they belong to the profile.
+    #[serde(default)]
+    pub rules: HashMap<String, bool>,
+}
+
+impl ValidationConfig {
+    /// Parses a configuration from TOML, rejecting unknown rule IDs
+    pub fn from_toml(input: &str) -> Result<Self, PlaylistError> {
+        let config: ValidationConfig =
+            toml::from_str(input).map_err(|err| PlaylistError::ConfigError(err.to_string()))?;
+
+        for id in config.rules.keys() {
+            if !all_rules().any(|rule| rule.id == id) {
+                return Err(PlaylistError::ConfigError(format!(
+                    "unknown rule \"{}\"",
+                    id
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads and parses a TOML configuration file
+    pub fn load(path: &Path) -> Result<Self, PlaylistError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// The rules that are on, in order
+    pub fn rules(&self) -> Vec<Rule> {
+        let profile_rules = self.profile.rules();
+        all_rules()
+            .filter(|rule| {
+                self.rules.get(rule.id).copied().unwrap_or_else(|| {
+                    profile_rules
+                        .iter()
+                        .any(|profile_rule| profile_rule.id == rule.id)
+                })
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Every rule the validator knows, in order
+fn all_rules() -> impl Iterator<Item = &'static Rule> {
+    RFC8216_RULES.iter().chain(APPLE_RULES)
+}
+
+impl MasterPlaylist {
+    /// Checks the playlist against the rules of RFC 8216
+    pub fn validate(&self) -> Vec<Finding> {
+        self.validate_rules(RFC8216_RULES)
+    }
+
+    /// Checks the playlist against the rules a configuration turns on
+    pub fn validate_with_config(&self, config: &ValidationConfig) -> Vec<Finding> {
+        self.validate_rules(&config.rules())
+    }
+
+    /// Checks the playlist against the given rules, in order
+    pub fn validate_rules(&self, rules: &[Rule]) -> Vec<Finding> {
+        rules
+            .iter()
+            .flat_map(|rule| {
+                (rule.check)(self)
+                    .into_iter()
+                    .map(|(location, message)| Finding {
+                        rule: rule.id,
+                        severity: rule.severity,
+                        location,
+                        message,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Playlist => write!(f, "playlist"),
+            Location::Variant(index) => write!(f, "EXT-X-STREAM-INF #{}", index + 1),
+            Location::Media(index) => write!(f, "EXT-X-MEDIA #{}", index + 1),
+            Location::IFrame(index) => write!(f, "EXT-X-I-FRAME-STREAM-INF #{}", index + 1),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            self.severity, self.rule, self.location, self.message
+        )
+    }
+}
+
+/// The renditions of the playlist with their index, grouped by type and GROUP-ID
+fn rendition_groups(
+    playlist: &MasterPlaylist,
+) -> HashMap<(MediaType, &str), Vec<(usize, &MediaTrack)>> {
+    let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+    for (index, track) in playlist.media.iter().enumerate() {
+        groups
+            .entry((track.track_type, track.group_id.as_str()))
+            .or_default()
+            .push((index, track));
+    }
+    groups
+}
+
+/// The findings of a check on each rendition on its own
+fn check_renditions(
+    playlist: &MasterPlaylist,
+    check: impl Fn(&MediaTrack) -> Option<String>,
+) -> Vec<(Location, String)> {
+    playlist
+        .media
+        .iter()
+        .enumerate()
+        .filter_map(|(index, track)| check(track).map(|message| (Location::Media(index), message)))
+        .collect()
+}
+
+fn check_undefined_groups(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    let groups = rendition_groups(playlist);
+    let mut findings = Vec::new();
+    for (index, variant) in playlist.variants.iter().enumerate() {
+        for (media_type, group_id) in variant.group_references() {
+            if !groups.contains_key(&(media_type, group_id)) {
+                findings.push((
+                    Location::Variant(index),
+                    format!("{} group \"{}\" is not defined", media_type, group_id),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+fn check_multiple_defaults(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    let mut findings = Vec::new();
+    for ((media_type, group_id), tracks) in rendition_groups(playlist) {
+        let defaults: Vec<_> = tracks
+            .iter()
+            .filter(|(_, track)| track.default == Some(true))
+            .collect();
+        for (index, _) in defaults.iter().skip(1) {
+            findings.push((
+                Location::Media(*index),
+                format!(
+                    "{} group \"{}\" already has a DEFAULT=YES rendition",
+                    media_type, group_id
+                ),
+            ));
+        }
+    }
+    findings.sort_by_key(|(location, _)| *location);
+    findings
+}
+
+fn check_default_not_autoselect(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_renditions(playlist, |track| {
+        (track.default == Some(true) && track.autoselect == Some(false))
+            .then(|| "DEFAULT=YES rendition has AUTOSELECT=NO".to_string())
+    })
+}
+
+fn check_duplicate_names(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    let mut findings = Vec::new();
+    for ((media_type, group_id), tracks) in rendition_groups(playlist) {
+        let mut names = HashSet::new();
+        for (index, track) in tracks {
+            if !names.insert(&track.name) {
+                findings.push((
+                    Location::Media(index),
+                    format!(
+                        "NAME \"{}\" is already used in {} group \"{}\"",
+                        track.name, media_type, group_id
+                    ),
+                ));
+            }
+        }
+    }
+    findings.sort_by_key(|(location, _)| *location);
+    findings
+}
+
+fn check_missing_instream_id(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_renditions(playlist, |track| {
+        (track.track_type == MediaType::ClosedCaptions && track.instream_id.is_none())
+            .then(|| "CLOSED-CAPTIONS rendition has no INSTREAM-ID".to_string())
+    })
+}
+
+fn check_unexpected_instream_id(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_renditions(playlist, |track| match track.track_type {
+        MediaType::ClosedCaptions => None,
+        media_type => track
+            .instream_id
+            .as_ref()
+            .map(|_| format!("{} rendition has an INSTREAM-ID", media_type)),
+    })
+}
+
+fn check_closed_captions_uri(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_renditions(playlist, |track| {
+        (track.track_type == MediaType::ClosedCaptions && track.uri.is_some())
+            .then(|| "CLOSED-CAPTIONS rendition has a URI".to_string())
+    })
+}
+
+fn check_unexpected_forced(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_renditions(playlist, |track| match track.track_type {
+        MediaType::Subtitles => None,
+        media_type => track
+            .forced
+            .map(|_| format!("{} rendition has a FORCED attribute", media_type)),
+    })
+}
+
+fn check_average_bandwidth(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    let mut findings = Vec::new();
+    for (index, variant) in playlist.variants.iter().enumerate() {
+        if let Some(average_bandwidth) = variant.average_bandwidth {
+            if average_bandwidth > variant.bandwidth {
+                findings.push((
+                    Location::Variant(index),
+                    format!(
+                        "AVERAGE-BANDWIDTH {} exceeds BANDWIDTH {}",
+                        average_bandwidth, variant.bandwidth
+                    ),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+fn check_mixed_closed_captions_none(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    let any_none = playlist
+        .variants
+        .iter()
+        .any(|variant| variant.closed_captions == Some(ClosedCaptions::None));
+    if !any_none {
+        return Vec::new();
+    }
+
+    playlist
+        .variants
+        .iter()
+        .enumerate()
+        .filter(|(_, variant)| variant.closed_captions != Some(ClosedCaptions::None))
+        .map(|(index, _)| {
+            (
+                Location::Variant(index),
+                "CLOSED-CAPTIONS is not NONE while other variant streams have NONE".to_string(),
+            )
+        })
+        .collect()
+}
+
+fn check_missing_codecs(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    playlist
+        .variants
+        .iter()
+        .enumerate()
+        .filter(|(_, variant)| variant.codecs.is_none())
+        .map(|(index, _)| (Location::Variant(index), "no CODECS attribute".to_string()))
+        .collect()
+}
+
+/// Whether a variant stream carries video, judged by its RESOLUTION or, failing
+/// that, by its CODECS. A variant stream without either, or with a codec of
+/// unknown type, is assumed to.
+fn is_video(variant: &StreamVariant) -> bool {
+    variant.resolution.is_some()
+        || variant.codecs.is_none()
+        || variant.codec_list().iter().any(|codec| {
+            !matches!(
+                codec.codec_type(),
+                Some(CodecType::Audio | CodecType::Subtitles)
+            )
+        })
+}
+
+/// The VIDEO-RANGE of a variant or I-frame stream, which defaults to SDR
+fn video_range(video_range: &Option<VideoRange>) -> &VideoRange {
+    video_range.as_ref().unwrap_or(&VideoRange::Sdr)
+}
+
+/// The findings of a check on each video variant stream on its own
+fn check_video_variants(
+    playlist: &MasterPlaylist,
+    check: impl Fn(&StreamVariant) -> Option<String>,
+) -> Vec<(Location, String)> {
+    playlist
+        .variants
+        .iter()
+        .enumerate()
+        .filter(|(_, variant)| is_video(variant))
+        .filter_map(|(index, variant)| {
+            check(variant).map(|message| (Location::Variant(index), message))
+        })
+        .collect()
+}
+
+fn check_video_codecs(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_video_variants(playlist, |variant| {
+        variant
+            .codecs
+            .is_none()
+            .then(|| "video variant stream has no CODECS attribute".to_string())
+    })
+}
+
+fn check_video_resolution(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_video_variants(playlist, |variant| {
+        variant
+            .resolution
+            .is_none()
+            .then(|| "video variant stream has no RESOLUTION attribute".to_string())
+    })
+}
+
+fn check_video_frame_rate(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_video_variants(playlist, |variant| {
+        variant
+            .frame_rate
+            .is_none()
+            .then(|| "video variant stream has no FRAME-RATE attribute".to_string())
+    })
+}
+
+fn check_missing_iframe_streams(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_video_variants(playlist, |variant| {
+        let matched = playlist.frames.iter().any(|frame| {
+            video_range(&frame.video_range) == video_range(&variant.video_range)
+                && (variant.resolution.is_none() || frame.resolution == variant.resolution)
+        });
+        (!matched).then(|| match variant.resolution {
+            Some((width, height)) => format!(
+                "no I-frame stream with RESOLUTION {}x{} and VIDEO-RANGE {}",
+                width,
+                height,
+                video_range(&variant.video_range)
+            ),
+            None => format!(
+                "no I-frame stream with VIDEO-RANGE {}",
+                video_range(&variant.video_range)
+            ),
+        })
+    })
+}
+
+fn check_hdr_without_sdr(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    check_video_variants(playlist, |variant| {
+        let range = video_range(&variant.video_range);
+        if *range == VideoRange::Sdr {
+            return None;
+        }
+        let paired = playlist.variants.iter().any(|other| {
+            is_video(other)
+                && *video_range(&other.video_range) == VideoRange::Sdr
+                && other.resolution == variant.resolution
+        });
+        (!paired).then(|| match variant.resolution {
+            Some((width, height)) => format!(
+                "{} variant stream has no SDR variant stream at {}x{}",
+                range, width, height
+            ),
+            None => format!("{} variant stream has no SDR variant stream", range),
+        })
+    })
+}
+
+fn check_ladder_step_ratio(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    // A ladder is the video variant streams of one VIDEO-RANGE that share an
+    // audio group, as ladders are usually repeated for each audio group
+    let mut ladders: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for (index, variant) in playlist.variants.iter().enumerate() {
+        if is_video(variant) {
+            ladders
+                .entry((video_range(&variant.video_range), variant.audio.as_deref()))
+                .or_default()
+                .push((index, variant.bandwidth));
+        }
+    }
+
+    let (min_ratio, max_ratio) = LADDER_STEP_RATIO;
+    let mut findings = Vec::new();
+    for mut ladder in ladders.into_values() {
+        ladder.sort_by_key(|(_, bandwidth)| *bandwidth);
+        for step in ladder.windows(2) {
+            let ((_, lower), (index, upper)) = (step[0], step[1]);
+            let ratio = upper as f64 / lower as f64;
+            if !(min_ratio..=max_ratio).contains(&ratio) {
+                findings.push((
+                    Location::Variant(index),
+                    format!(
+                        "BANDWIDTH {} is {:.2} times the next lower BANDWIDTH {}, outside {} to {}",
+                        upper, ratio, lower, min_ratio, max_ratio
+                    ),
+                ));
+            }
+        }
+    }
+    findings.sort_by_key(|(location, _)| *location);
+    findings
+}
+
+fn check_independent_segments(playlist: &MasterPlaylist) -> Vec<(Location, String)> {
+    if playlist.independent_segments {
+        Vec::new()
+    } else {
+        vec![(
+            Location::Playlist,
+            "no EXT-X-INDEPENDENT-SEGMENTS tag".to_string(),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_playlist;
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn test_validate_reports_rule_violations() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES,AUTOSELECT=NO,URI=\"en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES,FORCED=NO,URI=\"en2.m3u8\"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"English\",URI=\"cc.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,AVERAGE-BANDWIDTH=1200000,CODECS=\"avc1.64001f,mp4a.40.2\",AUDIO=\"aac\",SUBTITLES=\"subs\",CLOSED-CAPTIONS=NONE
+low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2000000,AUDIO=\"aac\",CLOSED-CAPTIONS=\"cc\"
+high.m3u8
+";
+        let playlist = parse_playlist(input).expect("Failed to parse playlist");
+        let findings: Vec<_> = playlist
+            .validate()
+            .into_iter()
+            .map(|finding| (finding.rule, finding.location))
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                ("undefined-group", Location::Variant(0)),
+                ("multiple-defaults", Location::Media(1)),
+                ("default-not-autoselect", Location::Media(0)),
+                ("duplicate-name", Location::Media(1)),
+                ("missing-instream-id", Location::Media(2)),
+                ("closed-captions-uri", Location::Media(2)),
+                ("unexpected-forced", Location::Media(1)),
+                ("average-bandwidth-exceeds-bandwidth", Location::Variant(0)),
+                ("mixed-closed-captions-none", Location::Variant(1)),
+                ("missing-codecs", Location::Variant(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_apple_profile() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,CODECS=\"avc1.64001f,mp4a.40.2\",RESOLUTION=1280x720,FRAME-RATE=30.000
+sdr_720.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,CODECS=\"hvc1.2.4.L120.90,mp4a.40.2\",RESOLUTION=1920x1080,VIDEO-RANGE=PQ
+pq_1080.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"
+audio.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=100000,CODECS=\"avc1.64001f\",RESOLUTION=1280x720,URI=\"sdr_720_iframe.m3u8\"
+";
+        let playlist = parse_playlist(input).expect("Failed to parse playlist");
+
+        let config = ValidationConfig::from_toml("profile = \"apple\"").unwrap();
+        let findings: Vec<_> = playlist
+            .validate_with_config(&config)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.location))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                ("video-frame-rate", Location::Variant(1)),
+                ("missing-iframe-stream", Location::Variant(1)),
+                ("hdr-without-sdr", Location::Variant(1)),
+                ("missing-independent-segments", Location::Playlist),
+            ]
+        );
+
+        // Rules can be turned off, and rules of other profiles turned on
+        let config = ValidationConfig::from_toml(
+            "[rules]\nmissing-codecs = false\nmissing-independent-segments = true",
+        )
+        .unwrap();
+        let rules: Vec<_> = playlist
+            .validate_with_config(&config)
+            .into_iter()
+            .map(|finding| finding.rule)
+            .collect();
+        assert_eq!(rules, ["missing-independent-segments"]);
+
+        assert!(matches!(
+            ValidationConfig::from_toml("[rules]\nno-such-rule = false"),
+            Err(PlaylistError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_ladder_step_ratio() {
+        let input = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1000000,RESOLUTION=640x360
+360.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1800000,RESOLUTION=960x540
+540.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4000000,RESOLUTION=1280x720
+720.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4400000,RESOLUTION=1920x1080
+1080.m3u8
+";
+        let playlist = parse_playlist(input).expect("Failed to parse playlist");
+
+        let ladder_rule: Vec<_> = APPLE_RULES
+            .iter()
+            .filter(|rule| rule.id == "ladder-step-ratio")
+            .copied()
+            .collect();
+        let locations: Vec<_> = playlist
+            .validate_rules(&ladder_rule)
+            .into_iter()
+            .map(|finding| finding.location)
+            .collect();
+        assert_eq!(locations, [Location::Variant(2), Location::Variant(3)]);
+    }
+
+    #[test]
+    fn test_validate_test_playlists() {
+        let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        file_path.push("tests/data/master_unenc_hdr10_all.m3u8");
+        let input = fs::read_to_string(file_path).expect("Failed to read test file");
+        let playlist = parse_playlist(&input).expect("Failed to parse playlist");
+
+        let findings = playlist.validate();
+        assert!(
+            findings
+                .iter()
+                .all(|finding| finding.severity == Severity::Warning),
+            "Unexpected findings: {:?}",
+            findings
+        );
+    }
+}
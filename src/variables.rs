@@ -0,0 +1,376 @@
+//! This module provides variable substitution for M3U8 master playlists that use the
+//! EXT-X-DEFINE tag. The parsed playlist keeps every `{$name}` reference as written, so that
+//! `write_to` reproduces the original text; `resolve_variables` produces a copy in which the
+//! references in URI lines and quoted-string attribute values are replaced by their values.
+//! Parsing already reports, with their location, the references to variables that no
+//! EXT-X-DEFINE tag defines and the variables defined more than once.
+
+use crate::errors::PlaylistError;
+use crate::parser::{
+    ClosedCaptions, Definition, ExtraAttribute, IFrameStream, MasterPlaylist, MediaTrack,
+    SessionData, SessionKey, StreamVariant,
+};
+use reqwest::Url;
+use std::collections::HashMap;
+
+impl MasterPlaylist {
+    /// Collects the variables defined by the EXT-X-DEFINE tags of the playlist.
+    ///
+    /// QUERYPARAM declarations take their value from the query string of
+    /// `playlist_url`, the URL the playlist was fetched from. A declaration
+    /// that cannot be satisfied, including any IMPORT declaration as a master
+    /// playlist has no parent to import from, is reported as an undefined
+    /// variable.
+    pub fn variables(
+        &self,
+        playlist_url: Option<&str>,
+    ) -> Result<HashMap<String, String>, PlaylistError> {
+        let query: Vec<(String, String)> = playlist_url
+            .and_then(|url| Url::parse(url).ok())
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default();
+
+        let mut variables = HashMap::new();
+        for define in &self.defines {
+            let (name, value) = match define.definition {
+                Definition::Value {
+                    ref name,
+                    ref value,
+                } => (name, Some(value.clone())),
+                Definition::QueryParam(ref name) => (
+                    name,
+                    query
+                        .iter()
+                        .find(|(key, _)| key == name)
+                        .map(|(_, value)| value.clone()),
+                ),
+                Definition::Import(ref name) => (name, None),
+            };
+            let value = value.ok_or_else(|| PlaylistError::UndefinedVariable {
+                name: name.clone(),
+                location: None,
+            })?;
+            variables.insert(name.clone(), value);
+        }
+
+        Ok(variables)
+    }
+
+    /// Returns a copy of the playlist with every variable reference replaced
+    /// by the value of the variable. Fails if a reference names a variable
+    /// that is not defined.
+    pub fn resolve_variables(
+        &self,
+        playlist_url: Option<&str>,
+    ) -> Result<MasterPlaylist, PlaylistError> {
+        let variables = self.variables(playlist_url)?;
+        let mut resolved = self.clone();
+
+        if let Some(ref mut steering) = resolved.content_steering {
+            steering.server_uri = substitute(&steering.server_uri, &variables)?;
+            resolve_optional(&mut steering.pathway_id, &variables)?;
+            resolve_extra_attributes(&mut steering.extra_attributes, &variables)?;
+        }
+        for data in &mut resolved.session_data {
+            resolve_session_data(data, &variables)?;
+        }
+        for key in &mut resolved.session_keys {
+            resolve_session_key(key, &variables)?;
+        }
+        for variant in &mut resolved.variants {
+            resolve_stream_variant(variant, &variables)?;
+        }
+        for track in &mut resolved.media {
+            resolve_media_track(track, &variables)?;
+        }
+        for frame in &mut resolved.frames {
+            resolve_iframe_stream(frame, &variables)?;
+        }
+
+        Ok(resolved)
+    }
+}
+
+fn resolve_stream_variant(
+    variant: &mut StreamVariant,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    for value in [
+        &mut variant.codecs,
+        &mut variant.supplemental_codecs,
+        &mut variant.allowed_cpc,
+        &mut variant.req_video_layout,
+        &mut variant.stable_variant_id,
+        &mut variant.audio,
+        &mut variant.video,
+        &mut variant.subtitles,
+        &mut variant.pathway_id,
+    ] {
+        resolve_optional(value, variables)?;
+    }
+    if let Some(ClosedCaptions::GroupId(ref mut group_id)) = variant.closed_captions {
+        *group_id = substitute(group_id, variables)?;
+    }
+    variant.uri = substitute(&variant.uri, variables)?;
+    resolve_extra_attributes(&mut variant.extra_attributes, variables)
+}
+
+fn resolve_media_track(
+    track: &mut MediaTrack,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    track.group_id = substitute(&track.group_id, variables)?;
+    track.name = substitute(&track.name, variables)?;
+    for value in [
+        &mut track.language,
+        &mut track.assoc_language,
+        &mut track.stable_rendition_id,
+        &mut track.instream_id,
+        &mut track.characteristics,
+        &mut track.channels,
+        &mut track.uri,
+    ] {
+        resolve_optional(value, variables)?;
+    }
+    resolve_extra_attributes(&mut track.extra_attributes, variables)
+}
+
+fn resolve_iframe_stream(
+    frame: &mut IFrameStream,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    resolve_optional(&mut frame.codecs, variables)?;
+    resolve_optional(&mut frame.pathway_id, variables)?;
+    frame.uri = substitute(&frame.uri, variables)?;
+    resolve_extra_attributes(&mut frame.extra_attributes, variables)
+}
+
+fn resolve_session_data(
+    data: &mut SessionData,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    data.data_id = substitute(&data.data_id, variables)?;
+    for value in [&mut data.value, &mut data.uri, &mut data.language] {
+        resolve_optional(value, variables)?;
+    }
+    resolve_extra_attributes(&mut data.extra_attributes, variables)
+}
+
+fn resolve_session_key(
+    key: &mut SessionKey,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    for value in [
+        &mut key.uri,
+        &mut key.iv,
+        &mut key.keyformat,
+        &mut key.keyformatversions,
+    ] {
+        resolve_optional(value, variables)?;
+    }
+    resolve_extra_attributes(&mut key.extra_attributes, variables)
+}
+
+fn resolve_optional(
+    value: &mut Option<String>,
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    if let Some(ref mut value) = value {
+        *value = substitute(value, variables)?;
+    }
+    Ok(())
+}
+
+/// Only quoted-string attribute values may contain variable references
+fn resolve_extra_attributes(
+    attributes: &mut [ExtraAttribute],
+    variables: &HashMap<String, String>,
+) -> Result<(), PlaylistError> {
+    for attribute in attributes.iter_mut().filter(|attribute| attribute.quoted) {
+        attribute.value = substitute(&attribute.value, variables)?;
+    }
+    Ok(())
+}
+
+/// Replaces every `{$name}` variable reference in `value`. Text that does
+/// not form a valid reference is left as it is.
+pub fn substitute(
+    value: &str,
+    variables: &HashMap<String, String>,
+) -> Result<String, PlaylistError> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("{$") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        match after
+            .find('}')
+            .map(|end| (&after[..end], &after[end + 1..]))
+        {
+            Some((name, remainder)) if is_variable_name(name) => {
+                let replacement =
+                    variables
+                        .get(name)
+                        .ok_or_else(|| PlaylistError::UndefinedVariable {
+                            name: name.to_string(),
+                            location: None,
+                        })?;
+                result.push_str(replacement);
+                rest = remainder;
+            }
+            _ => {
+                result.push_str("{$");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// The `{$name}` variable references in `text`, braces included
+pub(crate) fn variable_references(text: &str) -> Vec<&str> {
+    let mut references = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{$") {
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) if is_variable_name(&after[..end]) => {
+                references.push(&rest[start..start + end + 3]);
+                rest = &after[end + 1..];
+            }
+            _ => rest = after,
+        }
+    }
+
+    references
+}
+
+/// The name of a `{$name}` variable reference
+pub(crate) fn variable_name(reference: &str) -> &str {
+    &reference[2..reference.len() - 1]
+}
+
+/// Variable names consist of the characters [a-z], [A-Z], [0-9], '-' and '_'
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_playlist, parse_playlist_with_options, ParseOptions};
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXT-X-DEFINE:NAME=\"cdn\",VALUE=\"https://cdn-a.example.com\"
+#EXT-X-DEFINE:QUERYPARAM=\"token\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"{$cdn}/audio/vod.m3u8?token={$token}\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\"
+{$cdn}/video/vod.m3u8?token={$token}
+";
+
+    #[test]
+    fn test_resolve_variables() {
+        let playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        let resolved = playlist
+            .resolve_variables(Some("https://origin.example.com/master.m3u8?token=abc123"))
+            .expect("Failed to resolve variables");
+
+        assert_eq!(
+            resolved.variants[0].uri,
+            "https://cdn-a.example.com/video/vod.m3u8?token=abc123"
+        );
+        assert_eq!(
+            resolved.media[0].uri.as_deref(),
+            Some("https://cdn-a.example.com/audio/vod.m3u8?token=abc123")
+        );
+
+        // The raw view still writes the references as they were
+        let mut serialized_output = Vec::new();
+        playlist
+            .write_to(&mut serialized_output)
+            .expect("Failed to serialize playlist");
+        assert_eq!(String::from_utf8(serialized_output).unwrap(), PLAYLIST);
+    }
+
+    #[test]
+    fn test_resolve_missing_query_param() {
+        let playlist = parse_playlist(PLAYLIST).expect("Failed to parse playlist");
+        let result = playlist.resolve_variables(Some("https://origin.example.com/master.m3u8"));
+        assert!(
+            matches!(result, Err(PlaylistError::UndefinedVariable { name, .. }) if name == "token")
+        );
+    }
+
+    #[test]
+    fn test_parse_undefined_and_duplicate_variables() {
+        let input = "#EXTM3U
+#EXT-X-DEFINE:NAME=\"cdn\",VALUE=\"https://cdn-a.example.com\"
+#EXT-X-DEFINE:QUERYPARAM=\"cdn\"
+#X-NOTE:{$unused}
+#EXT-X-STREAM-INF:BANDWIDTH=1280000
+{$cdn}/video/vod.m3u8?token={$token}
+";
+        match parse_playlist_with_options(input, ParseOptions::strict()) {
+            Err(PlaylistError::DuplicateVariable(error)) => {
+                assert_eq!((error.line, error.column), (3, 27));
+                assert_eq!(error.attribute.as_deref(), Some("QUERYPARAM"));
+            }
+            other => panic!(
+                "Expected a duplicate variable but got {:?}",
+                other.map(|_| ())
+            ),
+        }
+
+        let (playlist, diagnostics) = parse_playlist_with_options(input, ParseOptions::default())
+            .expect("Lenient parsing should recover");
+        assert_eq!(playlist.defines.len(), 1);
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.error.line, d.error.column, d.recovery.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (3, 27, "skipped the tag"),
+                (6, 29, "kept the reference as written")
+            ]
+        );
+
+        let input = input.replace("#EXT-X-DEFINE:QUERYPARAM=\"cdn\"\n", "");
+        match parse_playlist_with_options(&input, ParseOptions::strict()) {
+            Err(PlaylistError::UndefinedVariable {
+                name,
+                location: Some(error),
+            }) => {
+                assert_eq!(name, "token");
+                assert_eq!((error.line, error.column, error.length), (5, 29, 8));
+            }
+            other => panic!(
+                "Expected an undefined variable but got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_substitute_undefined_variable() {
+        let variables = HashMap::from([("a".to_string(), "1".to_string())]);
+        assert_eq!(substitute("x{$a}y{$a}", &variables).unwrap(), "x1y1");
+        assert_eq!(
+            substitute("{$}{$not valid}", &variables).unwrap(),
+            "{$}{$not valid}"
+        );
+        assert!(matches!(
+            substitute("{$b}", &variables),
+            Err(PlaylistError::UndefinedVariable { name, .. }) if name == "b"
+        ));
+    }
+}